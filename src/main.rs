@@ -1,7 +1,8 @@
 mod shell;
 
 fn main() {
-    if let Err(err) = shell::run() {
-        eprintln!("Shell exited with error: {}", err);
+    match shell::run() {
+        Ok(status) => std::process::exit(status),
+        Err(err) => eprintln!("Shell exited with error: {}", err),
     }
 }
@@ -0,0 +1,423 @@
+use crate::shell::state::ShellState;
+use std::fmt;
+
+#[derive(Debug)]
+pub enum ArithError {
+    Syntax(String), // the token where parsing failed
+    DivisionByZero,
+    InvalidNumber(String),
+    NotAVariable,
+    Unbound(String),
+    TooDeep,
+}
+
+impl fmt::Display for ArithError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ArithError::Syntax(token) if token.is_empty() => {
+                write!(f, "syntax error: operand expected")
+            }
+            ArithError::Syntax(token) => {
+                write!(
+                    f,
+                    "syntax error in expression (error token is \"{}\")",
+                    token
+                )
+            }
+            ArithError::DivisionByZero => write!(f, "division by 0"),
+            ArithError::InvalidNumber(value) => write!(f, "{}: invalid number", value),
+            ArithError::NotAVariable => write!(f, "attempted assignment to non-variable"),
+            ArithError::Unbound(name) => write!(f, "{}: unbound variable", name),
+            ArithError::TooDeep => write!(f, "expression recursion level exceeded"),
+        }
+    }
+}
+
+// Variables holding expressions are evaluated in turn, up to this depth
+const MAX_DEPTH: usize = 64;
+
+const OPERATORS: [&str; 39] = [
+    "<<=", ">>=", "**", "++", "--", "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "+=", "-=",
+    "*=", "/=", "%=", "&=", "^=", "|=", "+", "-", "*", "/", "%", "<", ">", "=", "!", "~", "&", "^",
+    "|", "?", ":", "(", ")", ",",
+];
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number(String),
+    Name(String),
+    Op(&'static str),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Token::Number(s) | Token::Name(s) => write!(f, "{}", s),
+            Token::Op(op) => write!(f, "{}", op),
+        }
+    }
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, ArithError> {
+    let mut tokens = Vec::new();
+    let mut rest = input.trim_start();
+
+    while let Some(c) = rest.chars().next() {
+        let len = if c.is_ascii_alphanumeric() || c == '_' {
+            // Numbers may be 0x1f, 017 or base#digits, names are words
+            let len = rest
+                .find(|c: char| !c.is_ascii_alphanumeric() && c != '_' && c != '#')
+                .unwrap_or(rest.len());
+            let word = rest[..len].to_string();
+            tokens.push(if c.is_ascii_digit() {
+                Token::Number(word)
+            } else {
+                Token::Name(word)
+            });
+            len
+        } else if let Some(op) = OPERATORS.iter().find(|op| rest.starts_with(**op)) {
+            tokens.push(Token::Op(op));
+            op.len()
+        } else {
+            return Err(ArithError::Syntax(rest.to_string()));
+        };
+        rest = rest[len..].trim_start();
+    }
+
+    Ok(tokens)
+}
+
+fn parse_number(s: &str) -> Option<i64> {
+    let s = s.trim();
+    if let Some((base, digits)) = s.split_once('#') {
+        let base = base.parse::<u32>().ok().filter(|b| (2..=36).contains(b))?;
+        return i64::from_str_radix(digits, base).ok();
+    }
+    if let Some(hex) = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        return i64::from_str_radix(hex, 16).ok();
+    }
+    if s.len() > 1 && s.starts_with('0') {
+        return i64::from_str_radix(&s[1..], 8).ok();
+    }
+    s.parse().ok()
+}
+
+// Binary operators from loosest to tightest binding
+const BINARY_LEVELS: [&[&str]; 10] = [
+    &["||"],
+    &["&&"],
+    &["|"],
+    &["^"],
+    &["&"],
+    &["==", "!="],
+    &["<", "<=", ">", ">="],
+    &["<<", ">>"],
+    &["+", "-"],
+    &["*", "/", "%"],
+];
+
+// Recursive descent evaluator. While `skip` is set, as in the branch of
+// `&&`, `||` or `?:` that isn't taken, nothing is assigned and no errors
+// are raised for the values.
+struct Evaluator<'a> {
+    tokens: Vec<Token>,
+    pos: usize,
+    state: &'a mut ShellState,
+    skip: bool,
+    depth: usize,
+}
+
+impl Evaluator<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn peek_op(&self) -> Option<&'static str> {
+        match self.peek() {
+            Some(Token::Op(op)) => Some(op),
+            _ => None,
+        }
+    }
+
+    fn error(&self) -> ArithError {
+        ArithError::Syntax(self.peek().map(Token::to_string).unwrap_or_default())
+    }
+
+    fn expect(&mut self, op: &str) -> Result<(), ArithError> {
+        if self.peek_op() != Some(op) {
+            return Err(self.error());
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn variable(&mut self, name: &str) -> Result<i64, ArithError> {
+        let value = match self.state.get_var(name) {
+            Some(value) if !value.trim().is_empty() => value,
+            None if self.state.options.nounset && !self.skip => {
+                return Err(ArithError::Unbound(name.to_string()));
+            }
+            _ => return Ok(0),
+        };
+        if let Some(n) = parse_number(&value) {
+            return Ok(n);
+        }
+
+        // Like other shells, a variable can hold an expression
+        if self.depth >= MAX_DEPTH {
+            return Err(ArithError::TooDeep);
+        }
+        let tokens = tokenize(&value)?;
+        let mut inner = Evaluator {
+            tokens,
+            pos: 0,
+            state: self.state,
+            skip: self.skip,
+            depth: self.depth + 1,
+        };
+        inner.evaluate()
+    }
+
+    fn assign(&mut self, name: &str, value: i64) {
+        if !self.skip {
+            self.state.set_var(name, value.to_string());
+        }
+    }
+
+    fn evaluate(&mut self) -> Result<i64, ArithError> {
+        if self.tokens.is_empty() {
+            return Ok(0);
+        }
+        let value = self.comma()?;
+        if self.peek().is_some() {
+            return Err(self.error());
+        }
+        Ok(value)
+    }
+
+    fn comma(&mut self) -> Result<i64, ArithError> {
+        let mut value = self.assignment()?;
+        while self.peek_op() == Some(",") {
+            self.pos += 1;
+            value = self.assignment()?;
+        }
+        Ok(value)
+    }
+
+    fn assignment(&mut self) -> Result<i64, ArithError> {
+        let op = match self.tokens.get(self.pos + 1) {
+            Some(Token::Op(op)) if op.ends_with('=') && !["==", "!=", "<=", ">="].contains(op) => {
+                *op
+            }
+            _ => return self.ternary(),
+        };
+        let Some(Token::Name(name)) = self.peek().cloned() else {
+            return Err(ArithError::NotAVariable);
+        };
+        self.pos += 2;
+
+        let rhs = self.assignment()?;
+        let value = match op {
+            "=" => rhs,
+            _ => {
+                let current = self.variable(&name)?;
+                self.binary(&op[..op.len() - 1], current, rhs)?
+            }
+        };
+        self.assign(&name, value);
+        Ok(value)
+    }
+
+    fn ternary(&mut self) -> Result<i64, ArithError> {
+        let condition = self.binary_level(0)?;
+        if self.peek_op() != Some("?") {
+            return Ok(condition);
+        }
+        self.pos += 1;
+
+        let skip = self.skip;
+        self.skip = skip || condition == 0;
+        let then = self.assignment()?;
+        self.expect(":")?;
+        self.skip = skip || condition != 0;
+        let otherwise = self.assignment()?;
+        self.skip = skip;
+
+        Ok(if condition != 0 { then } else { otherwise })
+    }
+
+    fn binary_level(&mut self, level: usize) -> Result<i64, ArithError> {
+        if level == BINARY_LEVELS.len() {
+            return self.power();
+        }
+
+        let mut lhs = self.binary_level(level + 1)?;
+        while let Some(op) = self
+            .peek_op()
+            .filter(|op| BINARY_LEVELS[level].contains(op))
+        {
+            self.pos += 1;
+
+            // The right side of && and || only runs when it matters
+            let skip = self.skip;
+            match op {
+                "&&" => self.skip = skip || lhs == 0,
+                "||" => self.skip = skip || lhs != 0,
+                _ => {}
+            }
+            let rhs = self.binary_level(level + 1)?;
+            self.skip = skip;
+
+            lhs = self.binary(op, lhs, rhs)?;
+        }
+        Ok(lhs)
+    }
+
+    fn binary(&self, op: &str, lhs: i64, rhs: i64) -> Result<i64, ArithError> {
+        let value = match op {
+            "||" => i64::from(lhs != 0 || rhs != 0),
+            "&&" => i64::from(lhs != 0 && rhs != 0),
+            "|" => lhs | rhs,
+            "^" => lhs ^ rhs,
+            "&" => lhs & rhs,
+            "==" => i64::from(lhs == rhs),
+            "!=" => i64::from(lhs != rhs),
+            "<" => i64::from(lhs < rhs),
+            "<=" => i64::from(lhs <= rhs),
+            ">" => i64::from(lhs > rhs),
+            ">=" => i64::from(lhs >= rhs),
+            "<<" => lhs.wrapping_shl(rhs as u32),
+            ">>" => lhs.wrapping_shr(rhs as u32),
+            "+" => lhs.wrapping_add(rhs),
+            "-" => lhs.wrapping_sub(rhs),
+            "*" => lhs.wrapping_mul(rhs),
+            "/" | "%" if rhs == 0 => {
+                if self.skip {
+                    return Ok(0);
+                }
+                return Err(ArithError::DivisionByZero);
+            }
+            "/" => lhs.wrapping_div(rhs),
+            "%" => lhs.wrapping_rem(rhs),
+            _ => unreachable!(),
+        };
+        Ok(value)
+    }
+
+    // ** is right associative and binds looser than unary minus
+    fn power(&mut self) -> Result<i64, ArithError> {
+        let base = self.unary()?;
+        if self.peek_op() != Some("**") {
+            return Ok(base);
+        }
+        self.pos += 1;
+
+        let exponent = self.power()?;
+        if exponent < 0 {
+            if self.skip {
+                return Ok(0);
+            }
+            return Err(ArithError::Syntax("exponent less than 0".to_string()));
+        }
+        Ok(base.wrapping_pow(exponent.min(u32::MAX as i64) as u32))
+    }
+
+    fn unary(&mut self) -> Result<i64, ArithError> {
+        match self.peek_op() {
+            Some(op @ ("++" | "--"))
+                if matches!(self.tokens.get(self.pos + 1), Some(Token::Name(_))) =>
+            {
+                self.pos += 1;
+                let Some(Token::Name(name)) = self.peek().cloned() else {
+                    unreachable!()
+                };
+                self.pos += 1;
+                let step = if op == "++" { 1 } else { -1 };
+                let value = self.variable(&name)?.wrapping_add(step);
+                self.assign(&name, value);
+                Ok(value)
+            }
+            // Without a variable after them, ++ and -- are two signs
+            Some(op @ ("-" | "+" | "--" | "++")) => {
+                self.pos += 1;
+                let value = self.unary()?;
+                Ok(match op {
+                    "-" => value.wrapping_neg(),
+                    _ => value,
+                })
+            }
+            Some("!") => {
+                self.pos += 1;
+                Ok(i64::from(self.unary()? == 0))
+            }
+            Some("~") => {
+                self.pos += 1;
+                Ok(!self.unary()?)
+            }
+            _ => self.postfix(),
+        }
+    }
+
+    fn postfix(&mut self) -> Result<i64, ArithError> {
+        match self.peek().cloned() {
+            Some(Token::Number(s)) => {
+                self.pos += 1;
+                parse_number(&s).ok_or(ArithError::InvalidNumber(s))
+            }
+            Some(Token::Name(name)) => {
+                self.pos += 1;
+                let value = self.variable(&name)?;
+                if let Some(op @ ("++" | "--")) = self.peek_op() {
+                    self.pos += 1;
+                    let step = if op == "++" { 1 } else { -1 };
+                    self.assign(&name, value.wrapping_add(step));
+                }
+                Ok(value)
+            }
+            Some(Token::Op("(")) => {
+                self.pos += 1;
+                let value = self.comma()?;
+                self.expect(")")?;
+                Ok(value)
+            }
+            _ => Err(self.error()),
+        }
+    }
+}
+
+// Evaluate an integer expression with C-like operators, assigning to
+// shell variables as it goes
+pub fn evaluate(expr: &str, state: &mut ShellState) -> Result<i64, ArithError> {
+    let mut evaluator = Evaluator {
+        tokens: tokenize(expr)?,
+        pos: 0,
+        state,
+        skip: false,
+        depth: 0,
+    };
+    evaluator.evaluate()
+}
+
+// let expr... succeeds if the last expression is nonzero
+pub fn let_builtin(args: &[String], state: &mut ShellState) -> i32 {
+    if args.is_empty() {
+        eprintln!("let: expression expected");
+        return 1;
+    }
+
+    let mut value = 0;
+    for arg in args {
+        match evaluate(arg, state) {
+            Ok(result) => value = result,
+            Err(err) => {
+                eprintln!("let: {}: {}", arg, err);
+                if let ArithError::Unbound(_) = err {
+                    state.aborted = true;
+                }
+                return 1;
+            }
+        }
+    }
+
+    i32::from(value == 0)
+}
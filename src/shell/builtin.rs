@@ -1,23 +1,362 @@
+use crate::shell::arith;
+use crate::shell::condition;
+use crate::shell::dirstack;
+use crate::shell::echo;
+use crate::shell::eval::EvalResult;
+use crate::shell::exec;
+use crate::shell::expand::{is_valid_name, parse_assignment};
+use crate::shell::frecency;
+use crate::shell::jobs;
+use crate::shell::options;
+use crate::shell::pathcache;
+use crate::shell::read;
+use crate::shell::state::{Jump, ShellState};
+use crate::shell::trap;
+use crate::shell::ulimit;
+use crate::shell::umask;
 use std::env;
+use std::io;
+use std::path::{Path, PathBuf};
 
-pub fn handle_builtin(args: &[String]) -> Option<bool> {
+pub const BUILTINS: &[&str] = &[
+    "echo", "printf", "trap", "jobs", "wait", "kill", "exec", "exit", "cd", "pushd", "popd",
+    "dirs", "z", "type", "which", "command", "builtin", "hash", "rehash", "set", "export", "unset",
+    "let", "break", "continue", "local", "return", "test", "[", "read", "ulimit", "umask",
+];
+
+pub fn is_builtin(name: &str) -> bool {
+    BUILTINS.contains(&name)
+}
+
+pub fn handle_builtin(args: &[String], state: &mut ShellState) -> Option<EvalResult> {
+    let rest = &args[1..];
+
+    let status = match args.first()?.as_str() {
+        "exit" => return Some(exit(rest, state)),
+        "exec" => return Some(exec::exec(rest, state)),
+        "cd" => cd(rest, state),
+        "pushd" => dirstack::pushd(rest, state),
+        "popd" => dirstack::popd(rest, state),
+        "dirs" => dirstack::dirs(rest, state),
+        "z" => frecency::z(rest, state),
+        "type" => type_builtin(rest, state),
+        "which" => which(rest, state),
+        // `command NAME` itself is handled by eval_expr, only -v lands here
+        "command" => match rest.split_first() {
+            Some((flag, names)) if flag == "-v" => command_v(names, state),
+            _ => 0,
+        },
+        "hash" => pathcache::hash(rest, &mut state.path_cache.borrow_mut()),
+        "set" => options::set(rest, state),
+        "export" => export(rest, state),
+        "unset" => unset(rest, state),
+        "let" => arith::let_builtin(rest, state),
+        "break" | "continue" => loop_jump(&args[0], rest, state),
+        "local" => local(rest, state),
+        "test" | "[" => condition::test(&args[0], rest, state),
+        "return" => return_builtin(rest, state),
+        "echo" | "printf" => echo::run(args),
+        "trap" => trap::trap(rest, state),
+        "jobs" => jobs::jobs(rest, state),
+        "wait" => jobs::wait(rest, state),
+        "kill" => jobs::kill(rest, state),
+        "read" => read::read(rest, state),
+        "ulimit" => ulimit::ulimit(rest),
+        "umask" => umask::umask(rest),
+        "rehash" => {
+            state.path_cache.borrow_mut().clear();
+            0
+        }
+        "builtin" => match rest.first() {
+            Some(name) if is_builtin(name) => return handle_builtin(rest, state),
+            Some(name) => {
+                eprintln!("builtin: {}: not a shell builtin", name);
+                1
+            }
+            None => 0,
+        },
+        _ => return None,
+    };
+
+    Some(EvalResult::new(status))
+}
+
+// break [n] and continue [n], the loops act on it once the body stops
+fn loop_jump(name: &str, args: &[String], state: &mut ShellState) -> i32 {
+    let count = match args.first() {
+        None => 1,
+        Some(arg) => match arg.parse::<usize>() {
+            Ok(count) if count > 0 => count,
+            _ => {
+                eprintln!("{}: {}: loop count out of range", name, arg);
+                return 1;
+            }
+        },
+    };
+
+    if state.loop_depth == 0 {
+        eprintln!("{}: only meaningful in a loop", name);
+        return 0;
+    }
+
+    let count = count.min(state.loop_depth);
+    state.jump = Some(if name == "break" {
+        Jump::Break(count)
+    } else {
+        Jump::Continue(count)
+    });
+    0
+}
+
+// local name[=value]..., the old values come back when the function returns
+fn local(args: &[String], state: &mut ShellState) -> i32 {
+    if state.locals.is_empty() {
+        eprintln!("local: can only be used in a function");
+        return 1;
+    }
+
+    let mut status = 0;
+    for arg in args {
+        let (name, value) = match parse_assignment(arg) {
+            Some((name, value)) => (name, Some(value.to_string())),
+            None if is_valid_name(arg) => (arg.as_str(), None),
+            None => {
+                eprintln!("local: {}: not a valid identifier", arg);
+                status = 1;
+                continue;
+            }
+        };
+
+        let old_value = state.get_var(name);
+        let frame = state.locals.last_mut().unwrap();
+        frame.entry(name.to_string()).or_insert(old_value);
+
+        match value {
+            Some(value) => state.set_var(name, value),
+            None => state.unset_var(name),
+        }
+    }
+    status
+}
+
+// return [n] leaves the function with status n, or the last status
+fn return_builtin(args: &[String], state: &mut ShellState) -> i32 {
+    if state.locals.is_empty() {
+        eprintln!("return: can only be used in a function");
+        return 1;
+    }
+
+    let status = match args.first() {
+        Some(code) => match code.parse::<i32>() {
+            Ok(code) => code & 0xff,
+            Err(_) => {
+                eprintln!("return: {}: numeric argument required", code);
+                2
+            }
+        },
+        None => state.last_status,
+    };
+    state.jump = Some(Jump::Return);
+    status
+}
+
+fn exit(args: &[String], state: &ShellState) -> EvalResult {
+    let status = match args.first() {
+        Some(code) => match code.parse::<i32>() {
+            Ok(code) => code & 0xff,
+            Err(_) => {
+                eprintln!("exit: {}: numeric argument required", code);
+                2
+            }
+        },
+        None => state.last_status,
+    };
+
+    EvalResult {
+        status,
+        should_exit: true,
+    }
+}
+
+// export NAME[=value]...: move variables to the environment of children
+fn export(args: &[String], state: &mut ShellState) -> i32 {
+    if args.is_empty() {
+        for (name, value) in state.exported_vars() {
+            println!("export {}={}", name, value);
+        }
+        return 0;
+    }
+
+    let mut status = 0;
+    for arg in args {
+        let (name, value) = match parse_assignment(arg) {
+            Some((name, value)) => (name, Some(value.to_string())),
+            None if is_valid_name(arg) => (arg.as_str(), state.vars.get(arg).cloned()),
+            None => {
+                eprintln!("export: {}: not a valid identifier", arg);
+                status = 1;
+                continue;
+            }
+        };
+
+        let value = value.or_else(|| state.get_env(name)).unwrap_or_default();
+        state.set_env(name, value);
+    }
+    status
+}
+
+fn unset(args: &[String], state: &mut ShellState) -> i32 {
     match args.first().map(String::as_str) {
-        Some("exit") => Some(true),
-        Some("cd") => {
-            let new_dir = if args.len() > 1 {
-                args[1].clone()
-            } else if let Some(path) = dirs::home_dir() {
-                path.to_string_lossy().to_string()
-            } else {
-                "/".to_string()
-            };
+        Some("-f") => {
+            for name in &args[1..] {
+                state.functions.remove(name);
+            }
+        }
+        Some("-v") => args[1..].iter().for_each(|name| state.unset_var(name)),
+        _ => args.iter().for_each(|name| state.unset_var(name)),
+    }
+    0
+}
 
-            if let Err(err) = env::set_current_dir(&new_dir) {
-                eprintln!("cd: {}: {}", new_dir, err);
+// How a command name will be run
+pub enum Resolution {
+    Alias(String),
+    Function,
+    Builtin,
+    External(PathBuf),
+}
+
+pub fn resolve(name: &str, state: &ShellState) -> Option<Resolution> {
+    if let Some(alias) = state.config.aliases.get(name) {
+        return Some(Resolution::Alias(alias.clone()));
+    }
+    if state.functions.contains_key(name) {
+        return Some(Resolution::Function);
+    }
+    if is_builtin(name) {
+        return Some(Resolution::Builtin);
+    }
+    state
+        .path_cache
+        .borrow_mut()
+        .lookup(name)
+        .map(Resolution::External)
+}
+
+fn type_builtin(args: &[String], state: &ShellState) -> i32 {
+    let mut status = 0;
+    for name in args {
+        match resolve(name, state) {
+            Some(Resolution::Alias(alias)) => println!("{} is aliased to `{}'", name, alias),
+            Some(Resolution::Function) => println!("{} is a function", name),
+            Some(Resolution::Builtin) => println!("{} is a shell builtin", name),
+            Some(Resolution::External(path)) => println!("{} is {}", name, path.display()),
+            None => {
+                eprintln!("type: {}: not found", name);
+                status = 1;
+            }
+        }
+    }
+    status
+}
+
+fn which(args: &[String], state: &ShellState) -> i32 {
+    let mut status = 0;
+    for name in args {
+        match resolve(name, state) {
+            Some(Resolution::Alias(alias)) => println!("{}: aliased to {}", name, alias),
+            Some(Resolution::Function) => println!("{}: shell function", name),
+            Some(Resolution::Builtin) => println!("{}: shell built-in command", name),
+            Some(Resolution::External(path)) => println!("{}", path.display()),
+            None => {
+                eprintln!("{} not found", name);
+                status = 1;
             }
+        }
+    }
+    status
+}
 
-            Some(false)
+// Like type, but ignoring aliases and printing only the name or path
+fn command_v(args: &[String], state: &ShellState) -> i32 {
+    let mut status = 0;
+    for name in args {
+        if is_builtin(name) || state.functions.contains_key(name) {
+            println!("{}", name);
+        } else if let Some(path) = state.path_cache.borrow_mut().lookup(name) {
+            println!("{}", path.display());
+        } else {
+            status = 1;
         }
-        _ => None,
     }
+    status
+}
+
+fn cd(args: &[String], state: &mut ShellState) -> i32 {
+    let new_dir = match args.first().map(String::as_str) {
+        Some("-") => match state.get_env("OLDPWD") {
+            Some(dir) => {
+                println!("{}", dir);
+                dir
+            }
+            None => {
+                eprintln!("cd: OLDPWD not set");
+                return 1;
+            }
+        },
+        Some(dir) => dir.to_string(),
+        None => match state.home_dir() {
+            Some(path) => path.to_string_lossy().to_string(),
+            None => "/".to_string(),
+        },
+    };
+
+    match change_dir(Path::new(&new_dir), state) {
+        Ok(()) => 0,
+        Err(err) => {
+            eprintln!("cd: {}: {}", new_dir, err);
+            1
+        }
+    }
+}
+
+// Directory a lone word should cd into when autocd is enabled.
+// `...` goes up two levels, `....` three, and so on.
+pub fn autocd_target(args: &[String], state: &ShellState) -> Option<PathBuf> {
+    let [word] = args else {
+        return None;
+    };
+
+    if word.len() > 2 && word.chars().all(|c| c == '.') {
+        return Some(vec![".."; word.len() - 1].iter().collect());
+    }
+
+    let path = Path::new(word);
+    let is_bare_name = !word.contains('/');
+    if !path.is_dir()
+        || is_builtin(word)
+        || (is_bare_name && state.path_cache.borrow_mut().lookup(word).is_some())
+    {
+        return None;
+    }
+
+    Some(path.to_path_buf())
+}
+
+// Change the working directory, keep PWD and OLDPWD up to date and
+// remember the directory for z
+pub fn change_dir(path: &Path, state: &mut ShellState) -> io::Result<()> {
+    let old_dir = env::current_dir().ok();
+
+    env::set_current_dir(path)?;
+
+    if let Some(old_dir) = old_dir {
+        state.set_env("OLDPWD", old_dir.to_string_lossy().into_owned());
+    }
+    if let Ok(new_dir) = env::current_dir() {
+        state.frecency.borrow_mut().record(&new_dir);
+        state.set_env("PWD", new_dir.to_string_lossy().into_owned());
+    }
+
+    Ok(())
 }
@@ -1,52 +1,221 @@
-use std::process::{Command, Stdio};
+#[cfg(unix)]
+use crate::shell::signal::restore_default_signals;
+use crate::shell::state::ShellState;
+use crate::shell::time::wait_child;
+use std::collections::BTreeSet;
+use std::ffi::OsStr;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Stdio};
+use std::{env, fs};
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub enum CommandExpr {
     Sequence(Vec<CommandExpr>),              // a ; b ; c
     Pipeline(Vec<CommandExpr>),              // a | b | c
     And(Box<CommandExpr>, Box<CommandExpr>), // a && b
     Or(Box<CommandExpr>, Box<CommandExpr>),  // a || b
-    // Redirect {
-    //     command: Box<CommandExpr>,
-    //     kind: RedirectKind,
-    //     target: String,
-    // },
-    // Background(Box<CommandExpr>), // a &
-    Command(Vec<String>), // basic command + args
+    Redirect {
+        command: Box<CommandExpr>,
+        kind: RedirectKind,
+        target: String,
+    },
+    Background(Box<CommandExpr>), // a &
+    Time {
+        pipeline: Box<CommandExpr>,
+        posix: bool, // time -p
+    },
+    Command(Vec<String>),     // basic command + args
+    Group(Box<CommandExpr>),  // { a; b; }
+    Conditional(Vec<String>), // [[ words ]]
+    Function {
+        name: String,
+        body: Box<CommandExpr>,
+    },
+    If {
+        branches: Vec<(CommandExpr, CommandExpr)>, // if/elif conditions and bodies
+        otherwise: Option<Box<CommandExpr>>,       // else
+    },
+    While {
+        condition: Box<CommandExpr>,
+        body: Box<CommandExpr>,
+        until: bool, // loop while the condition fails instead
+    },
+    For {
+        name: String,
+        words: Option<Vec<String>>, // None without `in`
+        body: Box<CommandExpr>,
+    },
+    Case {
+        word: String,
+        arms: Vec<(Vec<String>, Option<CommandExpr>)>, // patterns and body
+    },
+}
+
+#[derive(Clone, Debug)]
+pub enum RedirectKind {
+    Duplicate(u32),           // n>&target
+    HereDoc { expand: bool }, // <<EOF, the target is the body
+    HereString,               // <<< word
 }
 
 #[cfg(windows)]
 fn is_builtin(cmd: &str) -> bool {
-    matches!(
-        cmd,
-        "echo" | "dir" | "cd" | "cls" | "set" | "pause" | "type"
-    )
+    matches!(cmd, "dir" | "cd" | "cls" | "set" | "pause" | "type")
 }
 
-pub fn execute_command(args: &[String]) -> bool {
-    if let Some((command, rest)) = args.split_first() {
+// Find the executable PATH would resolve a bare command name to
+pub fn find_in_path(name: &str, path_var: Option<&OsStr>) -> Option<PathBuf> {
+    env::split_paths(path_var?).find_map(|dir| {
+        let candidate = dir.join(name);
+
         #[cfg(windows)]
-        let result = if is_builtin(command) {
-            let mut full = vec![command.clone()];
-            full.extend(rest.iter().cloned());
-            Command::new("cmd").arg("/C").args(&full).status()
-        } else {
-            Command::new(command).args(rest).status()
-        };
+        {
+            if is_executable(&candidate) {
+                return Some(candidate);
+            }
+            // The extension is appended, python3.11 is found as python3.11.EXE
+            let extensions = env::var("PATHEXT").unwrap_or_else(|_| ".COM;.EXE;.BAT;.CMD".into());
+            extensions
+                .split(';')
+                .filter(|ext| !ext.is_empty())
+                .map(|ext| {
+                    let mut file = candidate.clone().into_os_string();
+                    file.push(ext);
+                    PathBuf::from(file)
+                })
+                .find(|p| is_executable(p))
+        }
 
         #[cfg(not(windows))]
-        let result = Command::new(command).args(rest).status();
+        is_executable(&candidate).then_some(candidate)
+    })
+}
 
-        match result {
-            Ok(status) => status.success(),
-            Err(err) => {
-                eprintln!("error executing command: {}", err);
-                false
-            }
+// Resolve a command name the way execute_command will, paths are used as-is
+pub fn resolve_program(name: &str, path_var: Option<&OsStr>) -> Option<PathBuf> {
+    if name.contains('/') || name.contains(std::path::MAIN_SEPARATOR) {
+        let path = Path::new(name);
+        return is_executable(path).then(|| path.to_path_buf());
+    }
+
+    find_in_path(name, path_var)
+}
+
+// Every executable name reachable through PATH
+pub fn path_executables(path_var: Option<&OsStr>) -> BTreeSet<String> {
+    let Some(paths) = path_var else {
+        return BTreeSet::new();
+    };
+
+    env::split_paths(paths)
+        .filter_map(|dir| fs::read_dir(dir).ok())
+        .flatten()
+        .filter_map(Result::ok)
+        .filter(|entry| is_executable(&entry.path()))
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .collect()
+}
+
+// `keep_fds` are pipe ends the child must inherit, for /dev/fd/N arguments
+fn new_command(
+    name: &str,
+    keep_fds: &[i32],
+    process_group: Option<u32>,
+    state: &ShellState,
+) -> Command {
+    let mut cmd = match state.path_cache.borrow_mut().lookup(name) {
+        Some(path) => Command::new(path),
+        None => Command::new(name),
+    };
+    state.pass_env(&mut cmd);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+
+        // Keep the name as typed in argv[0], like other shells do
+        cmd.arg0(name);
+
+        // Background jobs get their own group, 0 starts a new one
+        if let Some(group) = process_group {
+            cmd.process_group(group as i32);
+        }
+
+        // Pipes are opened close-on-exec, clear the flag on the ones to pass
+        let keep_fds = keep_fds.to_vec();
+
+        // SAFETY: only async-signal-safe calls happen between fork and exec
+        unsafe {
+            cmd.pre_exec(move || {
+                restore_default_signals();
+                for &fd in &keep_fds {
+                    if libc::fcntl(fd, libc::F_SETFD, 0) == -1 {
+                        return Err(io::Error::last_os_error());
+                    }
+                }
+                Ok(())
+            });
         }
-    } else {
-        false
     }
+
+    #[cfg(not(unix))]
+    let _ = (keep_fds, process_group);
+
+    cmd
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    path.metadata()
+        .map(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+// Run the command in place of the shell. On Unix this only returns if the
+// command couldn't be started, elsewhere it returns the command's status.
+pub fn exec_command(args: &[String], state: &mut ShellState) -> io::Result<i32> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+
+        let mut cmd = new_command(&args[0], &[], None, state);
+        Err(cmd.args(&args[1..]).exec())
+    }
+
+    #[cfg(not(unix))]
+    execute_command(args, &[], state).map(|status| status.code().unwrap_or(1))
+}
+
+pub fn execute_command(
+    args: &[String],
+    keep_fds: &[i32],
+    state: &mut ShellState,
+) -> io::Result<ExitStatus> {
+    let Some((command, rest)) = args.split_first() else {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Empty command"));
+    };
+
+    #[cfg(windows)]
+    if is_builtin(command) {
+        let mut full = vec![command.clone()];
+        full.extend(rest.iter().cloned());
+        let mut cmd = Command::new("cmd");
+        state.pass_env(&mut cmd);
+        return cmd.arg("/C").args(&full).status();
+    }
+
+    let mut child = new_command(command, keep_fds, None, state)
+        .args(rest)
+        .spawn()?;
+    wait_child(&mut child, state)
 }
 
 pub fn spawn_command(
@@ -54,6 +223,9 @@ pub fn spawn_command(
     stdin: Stdio,
     stdout: Stdio,
     stderr: Option<Stdio>,
+    keep_fds: &[i32],
+    process_group: Option<u32>,
+    state: &ShellState,
 ) -> Result<std::process::Child, std::io::Error> {
     if args.is_empty() {
         eprintln!("error: Attempted to spawn an empty command.");
@@ -84,7 +256,7 @@ pub fn spawn_command(
         (c.as_str(), rest.to_vec())
     };
 
-    let mut cmd = Command::new(command);
+    let mut cmd = new_command(command, keep_fds, process_group, state);
     cmd.args(cmd_args).stdin(stdin).stdout(stdout);
 
     if let Some(err) = stderr {
@@ -1,23 +1,96 @@
-use rustyline::completion::{Completer, FilenameCompleter};
-use rustyline::highlight::Highlighter;
+use crate::shell::builtin::BUILTINS;
+use crate::shell::frecency::SharedFrecencyDb;
+use crate::shell::pathcache::SharedPathCache;
+use rustyline::completion::{Completer, FilenameCompleter, Pair};
+use rustyline::highlight::{CmdKind, Highlighter};
 use rustyline::hint::Hinter;
 use rustyline::history::SearchDirection;
 use rustyline::validate::Validator;
 use rustyline::{Context, Helper};
 use std::borrow::Cow;
+use std::collections::BTreeSet;
 
 pub struct CommandHelper {
     completer: FilenameCompleter,
     hinter: CommandHinter,
+    aliases: Vec<String>,
+    path_cache: SharedPathCache,
+    frecency: SharedFrecencyDb,
 }
 
 impl CommandHelper {
-    pub fn new() -> Self {
+    pub fn new(
+        aliases: Vec<String>,
+        path_cache: SharedPathCache,
+        frecency: SharedFrecencyDb,
+    ) -> Self {
         CommandHelper {
             completer: FilenameCompleter::new(),
             hinter: CommandHinter::new(),
+            aliases,
+            path_cache,
+            frecency,
         }
     }
+
+    // Offer the directories z would jump to, replacing all fragments typed so far
+    fn complete_frecent_dirs(&self, fragments: &str) -> Vec<Pair> {
+        let fragments: Vec<String> = fragments.split_whitespace().map(String::from).collect();
+
+        self.frecency
+            .borrow()
+            .matches(&fragments)
+            .into_iter()
+            .map(|(_, path)| Pair {
+                display: path.display().to_string(),
+                replacement: path.display().to_string(),
+            })
+            .collect()
+    }
+
+    fn is_known_command(&self, name: &str) -> bool {
+        if BUILTINS.contains(&name) || self.aliases.iter().any(|a| a == name) {
+            return true;
+        }
+
+        let mut path_cache = self.path_cache.borrow_mut();
+        if name.contains('/') {
+            path_cache.lookup(name).is_some()
+        } else {
+            path_cache.executables().contains(name)
+        }
+    }
+
+    // Aliases, builtins and executables on PATH starting with the prefix
+    fn complete_command_names(&self, prefix: &str) -> Vec<Pair> {
+        let mut names: BTreeSet<String> = self.path_cache.borrow_mut().executables().clone();
+        names.extend(BUILTINS.iter().map(|s| s.to_string()));
+        names.extend(self.aliases.iter().cloned());
+
+        names
+            .into_iter()
+            .filter(|name| name.starts_with(prefix))
+            .map(|name| Pair {
+                display: name.clone(),
+                replacement: name,
+            })
+            .collect()
+    }
+}
+
+// Start of the word under the cursor, if that word is a command name
+fn command_word_start(line: &str, pos: usize) -> Option<usize> {
+    let before = &line[..pos];
+    let start = before
+        .rfind(|c: char| c.is_whitespace() || matches!(c, '|' | ';' | '&'))
+        .map(|i| i + 1)
+        .unwrap_or(0);
+
+    let preceding = before[..start].trim_end();
+    let in_command_position = preceding.is_empty() || preceding.ends_with(['|', ';', '&']);
+    let is_path = before[start..].contains('/');
+
+    (in_command_position && !is_path).then_some(start)
 }
 
 impl Completer for CommandHelper {
@@ -29,6 +102,14 @@ impl Completer for CommandHelper {
         pos: usize,
         ctx: &rustyline::Context<'_>,
     ) -> rustyline::Result<(usize, Vec<Self::Candidate>)> {
+        if let Some(fragments) = line[..pos].strip_prefix("z ") {
+            return Ok((pos - fragments.len(), self.complete_frecent_dirs(fragments)));
+        }
+
+        if let Some(start) = command_word_start(line, pos) {
+            return Ok((start, self.complete_command_names(&line[start..pos])));
+        }
+
         self.completer.complete(line, pos, ctx)
     }
 }
@@ -74,7 +155,73 @@ impl Hinter for CommandHelper {
     }
 }
 
+// Reserved words, and whether a command follows them
+const KEYWORDS: [(&str, bool); 17] = [
+    ("if", true),
+    ("then", true),
+    ("elif", true),
+    ("else", true),
+    ("fi", false),
+    ("while", true),
+    ("until", true),
+    ("do", true),
+    ("done", false),
+    ("for", false),
+    ("case", false),
+    ("esac", false),
+    ("function", false),
+    ("{", true),
+    ("}", false),
+    ("[[", false),
+    ("time", true),
+];
+
 impl Highlighter for CommandHelper {
+    // Color command names by whether they resolve to something runnable
+    fn highlight<'l>(&self, line: &'l str, _pos: usize) -> Cow<'l, str> {
+        let mut output = String::with_capacity(line.len());
+        let mut rest = line;
+        let mut in_command_position = true;
+
+        while !rest.is_empty() {
+            let c = rest.chars().next().unwrap();
+
+            if c.is_whitespace() || matches!(c, '|' | ';' | '&') {
+                if !c.is_whitespace() {
+                    in_command_position = true;
+                }
+                output.push(c);
+                rest = &rest[c.len_utf8()..];
+                continue;
+            }
+
+            let end = rest
+                .find(|c: char| c.is_whitespace() || matches!(c, '|' | ';' | '&'))
+                .unwrap_or(rest.len());
+            let word = &rest[..end];
+
+            if in_command_position
+                && let Some((_, takes_command)) = KEYWORDS.iter().find(|(kw, _)| *kw == word)
+            {
+                output.push_str(&format!("\x1b[35m{}\x1b[0m", word));
+                in_command_position = *takes_command;
+            } else if in_command_position {
+                let color = if self.is_known_command(word) { 32 } else { 31 };
+                output.push_str(&format!("\x1b[{}m{}\x1b[0m", color, word));
+                in_command_position = false;
+            } else {
+                output.push_str(word);
+            }
+            rest = &rest[end..];
+        }
+
+        Cow::Owned(output)
+    }
+
+    fn highlight_char(&self, _line: &str, _pos: usize, kind: CmdKind) -> bool {
+        kind != CmdKind::MoveCursor
+    }
+
     fn highlight_hint<'h>(&self, hint: &'h str) -> Cow<'h, str> {
         use Cow;
         Cow::Owned(format!("\x1b[90m{}\x1b[0m", hint))
@@ -83,3 +230,43 @@ impl Highlighter for CommandHelper {
 
 impl Validator for CommandHelper {}
 impl Helper for CommandHelper {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shell::frecency::{FrecencyDb, z_target};
+    use crate::shell::pathcache::PathCache;
+    use rustyline::history::DefaultHistory;
+    use std::cell::RefCell;
+    use std::fs;
+    use std::rc::Rc;
+
+    #[test]
+    fn z_goes_where_its_completion_points() {
+        let root = std::env::temp_dir().join(format!("ryn-z-{}", std::process::id()));
+        let dir = root.join("project");
+        fs::create_dir_all(&dir).unwrap();
+
+        // The default database has no file, recording doesn't touch the disk
+        let frecency = Rc::new(RefCell::new(FrecencyDb::default()));
+        frecency.borrow_mut().record(&dir);
+        let helper = CommandHelper::new(Vec::new(), PathCache::shared(), frecency.clone());
+
+        let line = "z proj";
+        let history = DefaultHistory::new();
+        let (start, pairs) = helper
+            .complete(line, line.len(), &Context::new(&history))
+            .unwrap();
+        assert_eq!(pairs.len(), 1);
+        let completed = format!("{}{}", &line[..start], pairs[0].replacement);
+
+        let args: Vec<String> = completed
+            .split_whitespace()
+            .skip(1)
+            .map(String::from)
+            .collect();
+        let target = z_target(&args, &frecency.borrow());
+        fs::remove_dir_all(&root).ok();
+        assert_eq!(target, Some(dir));
+    }
+}
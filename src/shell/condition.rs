@@ -0,0 +1,302 @@
+use crate::shell::expand::{expand_pattern, expand_string};
+use crate::shell::state::ShellState;
+use glob::Pattern;
+use std::fs::{self, Metadata};
+use std::io::IsTerminal;
+use std::path::Path;
+
+const UNARY_OPERATORS: [&str; 22] = [
+    "-e", "-f", "-d", "-s", "-r", "-w", "-x", "-L", "-h", "-p", "-S", "-b", "-c", "-u", "-g", "-k",
+    "-O", "-G", "-t", "-z", "-n", "-v",
+];
+
+const BINARY_OPERATORS: [&str; 14] = [
+    "=", "==", "!=", "<", ">", "-eq", "-ne", "-lt", "-le", "-gt", "-ge", "-nt", "-ot", "-ef",
+];
+
+// Status for a malformed expression, as opposed to a false one
+const ERROR_STATUS: i32 = 2;
+
+// Parses and evaluates the words of `test` or `[[ ]]` at the same time.
+// `test` gets words that were already expanded, `[[ ]]` expands each word
+// when it is needed, without splitting or globbing.
+struct Condition<'a> {
+    words: &'a [String],
+    pos: usize,
+    extended: bool,
+    state: &'a mut ShellState,
+}
+
+impl Condition<'_> {
+    fn peek(&self) -> Option<&str> {
+        self.words.get(self.pos).map(String::as_str)
+    }
+
+    fn next_word(&mut self) -> Result<&str, String> {
+        let word = self
+            .words
+            .get(self.pos)
+            .ok_or_else(|| "argument expected".to_string())?;
+        self.pos += 1;
+        Ok(word)
+    }
+
+    // The value of an operand
+    fn value(&mut self, word: &str) -> Result<String, String> {
+        if !self.extended {
+            return Ok(word.to_string());
+        }
+        expand_string(word, self.state).map_err(|err| err.to_string())
+    }
+
+    fn evaluate(&mut self) -> Result<bool, String> {
+        if self.words.is_empty() {
+            return Ok(false);
+        }
+        let result = self.or()?;
+        match self.peek() {
+            Some(word) => Err(format!("{}: unexpected argument", word)),
+            None => Ok(result),
+        }
+    }
+
+    fn or(&mut self) -> Result<bool, String> {
+        let or = if self.extended { "||" } else { "-o" };
+        let mut result = self.and()?;
+        while self.peek() == Some(or) {
+            self.pos += 1;
+            // Both sides are parsed, the right one only matters if needed
+            let rhs = self.and()?;
+            result = result || rhs;
+        }
+        Ok(result)
+    }
+
+    fn and(&mut self) -> Result<bool, String> {
+        let and = if self.extended { "&&" } else { "-a" };
+        let mut result = self.not()?;
+        while self.peek() == Some(and) {
+            self.pos += 1;
+            let rhs = self.not()?;
+            result = result && rhs;
+        }
+        Ok(result)
+    }
+
+    fn not(&mut self) -> Result<bool, String> {
+        // A lone `!` is just a non-empty string
+        if self.peek() == Some("!") && self.pos + 1 < self.words.len() {
+            self.pos += 1;
+            return Ok(!self.not()?);
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<bool, String> {
+        let remaining = self.words.len() - self.pos;
+
+        if self.peek() == Some("(") && remaining > 1 {
+            self.pos += 1;
+            let result = self.or()?;
+            if self.next_word()? != ")" {
+                return Err("`)' expected".to_string());
+            }
+            return Ok(result);
+        }
+
+        // `a = b` wins over `-f = b`, so binary operators are tried first
+        if remaining >= 3 && BINARY_OPERATORS.contains(&self.words[self.pos + 1].as_str()) {
+            let lhs = self.next_word()?.to_string();
+            let op = self.next_word()?.to_string();
+            let rhs = self.next_word()?.to_string();
+            return self.binary(&lhs, &op, &rhs);
+        }
+        if remaining >= 2 && self.peek() == Some("=~") {
+            return Err("=~: regular expressions are not supported".to_string());
+        }
+
+        let word = self.next_word()?.to_string();
+        if remaining >= 2 && UNARY_OPERATORS.contains(&word.as_str()) {
+            let operand = self.next_word()?.to_string();
+            let operand = self.value(&operand)?;
+            return self.unary(&word, &operand);
+        }
+
+        Ok(!self.value(&word)?.is_empty())
+    }
+
+    fn unary(&self, op: &str, operand: &str) -> Result<bool, String> {
+        let result = match op {
+            "-z" => operand.is_empty(),
+            "-n" => !operand.is_empty(),
+            "-v" => self.state.get_var(operand).is_some(),
+            "-t" => match operand.trim().parse::<i32>() {
+                Ok(0) => std::io::stdin().is_terminal(),
+                Ok(1) => std::io::stdout().is_terminal(),
+                Ok(2) => std::io::stderr().is_terminal(),
+                Ok(_) => false,
+                Err(_) => return Err(format!("{}: integer expression expected", operand)),
+            },
+            "-L" | "-h" => fs::symlink_metadata(operand).is_ok_and(|m| m.file_type().is_symlink()),
+            _ => match fs::metadata(operand) {
+                Ok(metadata) => file_test(op, Path::new(operand), &metadata),
+                Err(_) => false,
+            },
+        };
+        Ok(result)
+    }
+
+    fn binary(&mut self, lhs: &str, op: &str, rhs: &str) -> Result<bool, String> {
+        let lhs = self.value(lhs)?;
+
+        // In [[ ]] the right side of == and != is a pattern
+        if self.extended && matches!(op, "=" | "==" | "!=") {
+            let pattern = expand_pattern(rhs, self.state).map_err(|err| err.to_string())?;
+            let matches = match Pattern::new(&pattern) {
+                Ok(pattern) => pattern.matches(&lhs),
+                Err(_) => pattern == lhs,
+            };
+            return Ok(matches == (op != "!="));
+        }
+
+        let rhs = self.value(rhs)?;
+        let result = match op {
+            "=" | "==" => lhs == rhs,
+            "!=" => lhs != rhs,
+            "<" => lhs < rhs,
+            ">" => lhs > rhs,
+            "-nt" | "-ot" => {
+                let modified = |path: &str| fs::metadata(path).and_then(|m| m.modified()).ok();
+                match (modified(&lhs), modified(&rhs)) {
+                    (Some(a), Some(b)) if op == "-nt" => a > b,
+                    (Some(a), Some(b)) => a < b,
+                    (Some(_), None) => op == "-nt",
+                    (None, Some(_)) => op == "-ot",
+                    (None, None) => false,
+                }
+            }
+            "-ef" => same_file(&lhs, &rhs),
+            _ => {
+                let a = integer(&lhs)?;
+                let b = integer(&rhs)?;
+                match op {
+                    "-eq" => a == b,
+                    "-ne" => a != b,
+                    "-lt" => a < b,
+                    "-le" => a <= b,
+                    "-gt" => a > b,
+                    _ => a >= b,
+                }
+            }
+        };
+        Ok(result)
+    }
+}
+
+fn integer(s: &str) -> Result<i64, String> {
+    s.trim()
+        .parse()
+        .map_err(|_| format!("{}: integer expression expected", s))
+}
+
+#[cfg(unix)]
+fn file_test(op: &str, path: &Path, metadata: &Metadata) -> bool {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::fs::{FileTypeExt, MetadataExt};
+
+    let access = |mode| {
+        CString::new(path.as_os_str().as_bytes())
+            // SAFETY: the path is a valid NUL-terminated string
+            .is_ok_and(|path| unsafe { libc::access(path.as_ptr(), mode) } == 0)
+    };
+    let file_type = metadata.file_type();
+
+    match op {
+        "-e" => true,
+        "-f" => file_type.is_file(),
+        "-d" => file_type.is_dir(),
+        "-s" => metadata.len() > 0,
+        "-r" => access(libc::R_OK),
+        "-w" => access(libc::W_OK),
+        "-x" => access(libc::X_OK),
+        "-p" => file_type.is_fifo(),
+        "-S" => file_type.is_socket(),
+        "-b" => file_type.is_block_device(),
+        "-c" => file_type.is_char_device(),
+        "-u" => metadata.mode() & 0o4000 != 0,
+        "-g" => metadata.mode() & 0o2000 != 0,
+        "-k" => metadata.mode() & 0o1000 != 0,
+        // SAFETY: geteuid and getegid can't fail
+        "-O" => metadata.uid() == unsafe { libc::geteuid() },
+        "-G" => metadata.gid() == unsafe { libc::getegid() },
+        _ => false,
+    }
+}
+
+#[cfg(not(unix))]
+fn file_test(op: &str, _path: &Path, metadata: &Metadata) -> bool {
+    match op {
+        "-e" | "-r" | "-x" => true,
+        "-f" => metadata.is_file(),
+        "-d" => metadata.is_dir(),
+        "-s" => metadata.len() > 0,
+        "-w" => !metadata.permissions().readonly(),
+        _ => false,
+    }
+}
+
+#[cfg(unix)]
+fn same_file(a: &str, b: &str) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    match (fs::metadata(a), fs::metadata(b)) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+        _ => false,
+    }
+}
+
+#[cfg(not(unix))]
+fn same_file(a: &str, b: &str) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+fn run(name: &str, words: &[String], extended: bool, state: &mut ShellState) -> i32 {
+    let mut condition = Condition {
+        words,
+        pos: 0,
+        extended,
+        state,
+    };
+    match condition.evaluate() {
+        Ok(result) => i32::from(!result),
+        Err(err) => {
+            eprintln!("{}: {}", name, err);
+            ERROR_STATUS
+        }
+    }
+}
+
+// test expr and [ expr ]
+pub fn test(name: &str, args: &[String], state: &mut ShellState) -> i32 {
+    let args = if name == "[" {
+        match args.split_last() {
+            Some((last, rest)) if last == "]" => rest,
+            _ => {
+                eprintln!("[: missing `]'");
+                return ERROR_STATUS;
+            }
+        }
+    } else {
+        args
+    };
+    run(name, args, false, state)
+}
+
+// [[ expr ]], with && || and patterns, and words expanded without splitting
+pub fn extended_test(words: &[String], state: &mut ShellState) -> i32 {
+    run("[[", words, true, state)
+}
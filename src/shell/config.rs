@@ -2,12 +2,18 @@ use colored::*;
 use std::collections::HashMap;
 use std::fmt;
 use std::str::FromStr;
+use std::time::Duration;
 use std::{fs, io};
 
 pub struct Config {
     pub prompt: String,
     pub cursor: CursorStyle,
     pub aliases: HashMap<String, String>,
+    pub autocd: bool,
+    pub command_not_found_handler: Option<String>,
+    pub time_format: String,
+    pub notify_after: Option<Duration>, // notify when a command runs longer
+    pub notify_style: Vec<NotifyStyle>,
 }
 
 pub enum CursorStyle {
@@ -32,12 +38,35 @@ impl CursorStyle {
     }
 }
 
+// How a long command announces that it finished
+#[derive(Clone, Copy)]
+pub enum NotifyStyle {
+    Bell,   // the terminal bell
+    Osc9,   // desktop notification in iTerm2, kitty, WezTerm, Windows Terminal...
+    Osc777, // desktop notification in urxvt, foot, Ghostty...
+}
+
+impl NotifyStyle {
+    pub fn to_ansi_code(self, title: &str, message: &str) -> String {
+        match self {
+            NotifyStyle::Bell => "\x07".to_string(),
+            NotifyStyle::Osc9 => format!("\x1b]9;{}: {}\x07", title, message),
+            NotifyStyle::Osc777 => format!("\x1b]777;notify;{};{}\x07", title, message),
+        }
+    }
+}
+
 impl Default for Config {
     fn default() -> Self {
         Self {
             prompt: "{time24} {user ifnotgit} {host ifnotgit}{git} > ".into(),
             cursor: CursorStyle::BlinkingBar,
             aliases: HashMap::new(),
+            autocd: false,
+            command_not_found_handler: None,
+            time_format: "\\nreal\\t%3lR\\nuser\\t%3lU\\nsys\\t%3lS\\nmaxrss\\t%MK".into(),
+            notify_after: None,
+            notify_style: vec![NotifyStyle::Bell, NotifyStyle::Osc9],
         }
     }
 }
@@ -58,6 +87,19 @@ impl FromStr for CursorStyle {
     }
 }
 
+impl FromStr for NotifyStyle {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "bell" => Ok(NotifyStyle::Bell),
+            "osc9" => Ok(NotifyStyle::Osc9),
+            "osc777" => Ok(NotifyStyle::Osc777),
+            _ => Err(()),
+        }
+    }
+}
+
 struct SyntaxError {
     line_number: usize,
     line: String,
@@ -139,6 +181,43 @@ pub fn load_config() -> Result<Config, io::Error> {
                     return Ok(Config::default());
                 }
             },
+            "command_not_found_handler" => {
+                config.command_not_found_handler = Some(value.to_string())
+            }
+            "time_format" => config.time_format = value.to_string(),
+            "notify_after" => match humantime::parse_duration(value) {
+                Ok(duration) => config.notify_after = Some(duration),
+                Err(err) => {
+                    print_syntax_error(SyntaxError {
+                        line_number,
+                        message: format!("Invalid duration '{}': {}", value, err),
+                        line: trimmed.to_string(),
+                    });
+                    return Ok(Config::default());
+                }
+            },
+            "notify_style" => match value.split(',').map(str::parse).collect() {
+                Ok(styles) => config.notify_style = styles,
+                Err(_) => {
+                    print_syntax_error(SyntaxError {
+                        line_number,
+                        message: format!("Invalid notify style: '{}'", value),
+                        line: trimmed.to_string(),
+                    });
+                    return Ok(Config::default());
+                }
+            },
+            "autocd" => match value.parse::<bool>() {
+                Ok(enabled) => config.autocd = enabled,
+                Err(_) => {
+                    print_syntax_error(SyntaxError {
+                        line_number,
+                        message: format!("Expected true or false, got '{}'", value),
+                        line: trimmed.to_string(),
+                    });
+                    return Ok(Config::default());
+                }
+            },
             _ => {
                 print_syntax_error(SyntaxError {
                     line_number,
@@ -0,0 +1,192 @@
+use crate::shell::builtin::change_dir;
+use crate::shell::state::ShellState;
+use std::env;
+use std::path::{Path, PathBuf};
+
+// Directories saved by pushd, the current directory is not stored here
+#[derive(Default)]
+pub struct DirStack {
+    entries: Vec<PathBuf>,
+}
+
+impl DirStack {
+    pub fn depth(&self) -> usize {
+        self.entries.len()
+    }
+
+    // Full stack as shown by dirs: the current directory first, then saved entries
+    fn full(&self) -> Vec<PathBuf> {
+        let mut full = vec![env::current_dir().unwrap_or_default()];
+        full.extend(self.entries.iter().cloned());
+        full
+    }
+}
+
+enum StackIndex {
+    FromLeft(usize),  // +n
+    FromRight(usize), // -n
+}
+
+fn parse_index(arg: &str) -> Option<StackIndex> {
+    if let Some(n) = arg.strip_prefix('+') {
+        n.parse().ok().map(StackIndex::FromLeft)
+    } else if let Some(n) = arg.strip_prefix('-') {
+        n.parse().ok().map(StackIndex::FromRight)
+    } else {
+        None
+    }
+}
+
+// Resolve +n/-n to a position in the full stack
+fn resolve_index(index: StackIndex, len: usize) -> Option<usize> {
+    match index {
+        StackIndex::FromLeft(n) if n < len => Some(n),
+        StackIndex::FromRight(n) if n < len => Some(len - 1 - n),
+        _ => None,
+    }
+}
+
+pub fn display_path(path: &Path) -> String {
+    if let Some(home) = dirs::home_dir()
+        && let Ok(rest) = path.strip_prefix(&home)
+    {
+        if rest.as_os_str().is_empty() {
+            return "~".to_string();
+        }
+        return format!("~/{}", rest.display());
+    }
+    path.display().to_string()
+}
+
+fn print_stack(state: &ShellState) {
+    let line: Vec<String> = state
+        .dir_stack
+        .full()
+        .iter()
+        .map(|p| display_path(p))
+        .collect();
+    println!("{}", line.join(" "));
+}
+
+pub fn pushd(args: &[String], state: &mut ShellState) -> i32 {
+    let cwd = env::current_dir().unwrap_or_default();
+
+    match args.first() {
+        // Swap the two top directories
+        None => {
+            if state.dir_stack.entries.is_empty() {
+                eprintln!("pushd: no other directory");
+                return 1;
+            }
+            let target = state.dir_stack.entries[0].clone();
+            if let Err(err) = change_dir(&target, state) {
+                eprintln!("pushd: {}: {}", target.display(), err);
+                return 1;
+            }
+            state.dir_stack.entries[0] = cwd;
+        }
+        Some(arg) => {
+            if let Some(index) = parse_index(arg) {
+                let mut full = state.dir_stack.full();
+                let Some(n) = resolve_index(index, full.len()) else {
+                    eprintln!("pushd: {}: directory stack index out of range", arg);
+                    return 1;
+                };
+                full.rotate_left(n);
+                if let Err(err) = change_dir(&full[0], state) {
+                    eprintln!("pushd: {}: {}", full[0].display(), err);
+                    return 1;
+                }
+                state.dir_stack.entries = full.split_off(1);
+            } else {
+                if let Err(err) = change_dir(Path::new(arg), state) {
+                    eprintln!("pushd: {}: {}", arg, err);
+                    return 1;
+                }
+                state.dir_stack.entries.insert(0, cwd);
+            }
+        }
+    }
+
+    print_stack(state);
+    0
+}
+
+pub fn popd(args: &[String], state: &mut ShellState) -> i32 {
+    if state.dir_stack.entries.is_empty() {
+        eprintln!("popd: directory stack empty");
+        return 1;
+    }
+
+    let n = match args.first() {
+        None => 0,
+        Some(arg) => {
+            let len = state.dir_stack.entries.len() + 1;
+            match parse_index(arg).and_then(|index| resolve_index(index, len)) {
+                Some(n) => n,
+                None => {
+                    eprintln!("popd: {}: invalid argument", arg);
+                    return 1;
+                }
+            }
+        }
+    };
+
+    if n == 0 {
+        // Drop the current directory and move to the next one
+        let target = state.dir_stack.entries[0].clone();
+        if let Err(err) = change_dir(&target, state) {
+            eprintln!("popd: {}: {}", target.display(), err);
+            return 1;
+        }
+        state.dir_stack.entries.remove(0);
+    } else {
+        state.dir_stack.entries.remove(n - 1);
+    }
+
+    print_stack(state);
+    0
+}
+
+pub fn dirs(args: &[String], state: &mut ShellState) -> i32 {
+    let mut verbose = false;
+    let mut per_line = false;
+
+    for arg in args {
+        match arg.as_str() {
+            "-c" => {
+                state.dir_stack.entries.clear();
+                return 0;
+            }
+            "-v" => verbose = true,
+            "-p" => per_line = true,
+            _ => {
+                let full = state.dir_stack.full();
+                return match parse_index(arg).and_then(|index| resolve_index(index, full.len())) {
+                    Some(n) => {
+                        println!("{}", display_path(&full[n]));
+                        0
+                    }
+                    None => {
+                        eprintln!("dirs: {}: invalid argument", arg);
+                        1
+                    }
+                };
+            }
+        }
+    }
+
+    if verbose {
+        for (i, path) in state.dir_stack.full().iter().enumerate() {
+            println!("{:2}  {}", i, display_path(path));
+        }
+    } else if per_line {
+        for path in state.dir_stack.full() {
+            println!("{}", display_path(&path));
+        }
+    } else {
+        print_stack(state);
+    }
+
+    0
+}
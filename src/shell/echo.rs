@@ -0,0 +1,392 @@
+use crate::shell::expand::quote;
+use std::io::{self, Write};
+use std::iter::Peekable;
+use std::str::Chars;
+
+// echo and printf only produce output, so it is built up front. Pipelines
+// write it to their pipe instead of spawning a process.
+pub fn output(args: &[String]) -> Option<(Vec<u8>, i32)> {
+    match args[0].as_str() {
+        "echo" => Some((echo(&args[1..]), 0)),
+        "printf" => Some(printf(&args[1..])),
+        _ => None,
+    }
+}
+
+// echo and printf as builtins, writing to the shell's stdout
+pub fn run(args: &[String]) -> i32 {
+    let (output, status) = output(args).unwrap_or_default();
+    let mut stdout = io::stdout().lock();
+    match stdout.write_all(&output).and_then(|_| stdout.flush()) {
+        Ok(()) => status,
+        // The reader went away, as in `echo x | true`
+        Err(err) if err.kind() == io::ErrorKind::BrokenPipe => 1,
+        Err(err) => {
+            eprintln!("{}: write error: {}", args[0], err);
+            1
+        }
+    }
+}
+
+// echo [-neE] [arg...]
+fn echo(args: &[String]) -> Vec<u8> {
+    let mut newline = true;
+    let mut escapes = false;
+
+    // Only words made of known flags are options, `echo -x` prints -x
+    let mut i = 0;
+    while let Some(arg) = args.get(i) {
+        let Some(flags) = arg.strip_prefix('-') else {
+            break;
+        };
+        if flags.is_empty() || !flags.chars().all(|c| "neE".contains(c)) {
+            break;
+        }
+        for flag in flags.chars() {
+            match flag {
+                'n' => newline = false,
+                'e' => escapes = true,
+                _ => escapes = false,
+            }
+        }
+        i += 1;
+    }
+
+    let mut output = Vec::new();
+    for (n, arg) in args[i..].iter().enumerate() {
+        if n > 0 {
+            output.push(b' ');
+        }
+        if !escapes {
+            output.extend_from_slice(arg.as_bytes());
+        } else if unescape(arg, true, &mut output) {
+            // \c stops the output, the newline included
+            return output;
+        }
+    }
+    if newline {
+        output.push(b'\n');
+    }
+    output
+}
+
+// Expands backslash escapes into the output, returns true on \c
+fn unescape(s: &str, echo_octal: bool, output: &mut Vec<u8>) -> bool {
+    let mut chars = s.chars().peekable();
+    let mut buf = [0; 4];
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            output.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+        } else if escape(&mut chars, echo_octal, output) {
+            return true;
+        }
+    }
+    false
+}
+
+// The escape after a backslash, returns true on \c. Octal escapes are
+// \0nnn for echo and %b, and \nnn in printf formats.
+fn escape(chars: &mut Peekable<Chars>, echo_octal: bool, output: &mut Vec<u8>) -> bool {
+    let mut buf = [0; 4];
+    let Some(escape) = chars.next() else {
+        output.push(b'\\');
+        return false;
+    };
+
+    let mut digits = |radix: u32, max_digits: usize, initial: u32| {
+        let mut value = initial;
+        let mut count = 0;
+        while count < max_digits {
+            let Some(digit) = chars.peek().and_then(|c| c.to_digit(radix)) else {
+                break;
+            };
+            value = value * radix + digit;
+            count += 1;
+            chars.next();
+        }
+        (value, count)
+    };
+
+    let byte = match escape {
+        '\\' => b'\\',
+        'a' => 0x07,
+        'b' => 0x08,
+        'e' | 'E' => 0x1b,
+        'f' => 0x0c,
+        'n' => b'\n',
+        'r' => b'\r',
+        't' => b'\t',
+        'v' => 0x0b,
+        'c' => return true,
+        '0' if echo_octal => digits(8, 3, 0).0 as u8,
+        '0'..='7' => digits(8, 2, escape.to_digit(8).unwrap()).0 as u8,
+        'x' | 'u' | 'U' => {
+            let max_digits = match escape {
+                'x' => 2,
+                'u' => 4,
+                _ => 8,
+            };
+            match digits(16, max_digits, 0) {
+                // Not an escape after all
+                (_, 0) => {
+                    output.push(b'\\');
+                    output.extend_from_slice(escape.encode_utf8(&mut buf).as_bytes());
+                    return false;
+                }
+                (value, _) if escape == 'x' => value as u8,
+                (value, _) => {
+                    let c = char::from_u32(value).unwrap_or(char::REPLACEMENT_CHARACTER);
+                    output.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                    return false;
+                }
+            }
+        }
+        other => {
+            output.push(b'\\');
+            output.extend_from_slice(other.encode_utf8(&mut buf).as_bytes());
+            return false;
+        }
+    };
+    output.push(byte);
+    false
+}
+
+// A conversion like %-8.3s
+#[derive(Default)]
+struct Spec {
+    left: bool,
+    plus: bool,
+    space: bool,
+    alternate: bool,
+    zero: bool,
+    width: Option<usize>,
+    precision: Option<usize>,
+}
+
+// printf format [argument...]
+fn printf(args: &[String]) -> (Vec<u8>, i32) {
+    let Some((format, mut args)) = args.split_first() else {
+        eprintln!("printf: usage: printf format [arguments]");
+        return (Vec::new(), 1);
+    };
+
+    let mut output = Vec::new();
+    let mut status = 0;
+
+    // The format is reused until every argument has been consumed
+    loop {
+        let remaining = args.len();
+        match format_once(format, &mut args, &mut output, &mut status) {
+            Ok(false) if !args.is_empty() && args.len() < remaining => {}
+            Ok(_) => break,
+            Err(err) => {
+                eprintln!("printf: {}", err);
+                return (output, 1);
+            }
+        }
+    }
+    (output, status)
+}
+
+fn next_arg<'a>(args: &mut &'a [String]) -> Option<&'a str> {
+    let (first, rest) = args.split_first()?;
+    *args = rest;
+    Some(first)
+}
+
+// A width or precision, * takes it from the next argument
+fn spec_number(
+    chars: &mut Peekable<Chars>,
+    args: &mut &[String],
+    status: &mut i32,
+) -> Option<usize> {
+    if chars.next_if_eq(&'*').is_some() {
+        let value = integer(next_arg(args).unwrap_or("0"), status);
+        return Some(value.unsigned_abs() as usize);
+    }
+    let mut value = None;
+    while let Some(digit) = chars.peek().and_then(|c| c.to_digit(10)) {
+        value = Some(value.unwrap_or(0) * 10 + digit as usize);
+        chars.next();
+    }
+    value
+}
+
+// Outputs the format once, returns true if \c stopped the output
+fn format_once(
+    format: &str,
+    args: &mut &[String],
+    output: &mut Vec<u8>,
+    status: &mut i32,
+) -> Result<bool, String> {
+    let mut chars = format.chars().peekable();
+    let mut buf = [0; 4];
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if escape(&mut chars, false, output) => return Ok(true),
+            '\\' => continue,
+            '%' => {}
+            c => {
+                output.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                continue;
+            }
+        }
+
+        let mut spec = Spec::default();
+        while let Some(flag) = chars.next_if(|c| "-+ #0".contains(*c)) {
+            match flag {
+                '-' => spec.left = true,
+                '+' => spec.plus = true,
+                ' ' => spec.space = true,
+                '#' => spec.alternate = true,
+                _ => spec.zero = true,
+            }
+        }
+        spec.width = spec_number(&mut chars, args, status);
+        if chars.next_if_eq(&'.').is_some() {
+            spec.precision = Some(spec_number(&mut chars, args, status).unwrap_or(0));
+        }
+
+        let Some(conversion) = chars.next() else {
+            return Err("`%': missing format character".to_string());
+        };
+        let text = match conversion {
+            '%' => "%".to_string(),
+            's' => {
+                let arg = next_arg(args).unwrap_or_default();
+                match spec.precision {
+                    Some(precision) => arg.chars().take(precision).collect(),
+                    None => arg.to_string(),
+                }
+            }
+            'b' => {
+                let mut bytes = Vec::new();
+                let stop = unescape(next_arg(args).unwrap_or_default(), true, &mut bytes);
+                output.extend(pad(&String::from_utf8_lossy(&bytes), &spec).into_bytes());
+                if stop {
+                    return Ok(true);
+                }
+                continue;
+            }
+            'q' => quote(next_arg(args).unwrap_or_default()),
+            'c' => next_arg(args)
+                .and_then(|arg| arg.chars().next())
+                .map(String::from)
+                .unwrap_or_default(),
+            'd' | 'i' => {
+                let value = integer(next_arg(args).unwrap_or("0"), status);
+                let digits = min_digits(value.unsigned_abs().to_string(), &spec);
+                number_text(sign(value < 0, &spec), "", &digits, &spec)
+            }
+            'u' | 'o' | 'x' | 'X' => {
+                // Negative numbers wrap around, as in C
+                let value = integer(next_arg(args).unwrap_or("0"), status) as u64;
+                let (digits, prefix) = match conversion {
+                    'u' => (value.to_string(), ""),
+                    'o' => (format!("{:o}", value), "0"),
+                    'x' => (format!("{:x}", value), "0x"),
+                    _ => (format!("{:X}", value), "0X"),
+                };
+                let prefix = if spec.alternate && value != 0 {
+                    prefix
+                } else {
+                    ""
+                };
+                number_text("", prefix, &min_digits(digits, &spec), &spec)
+            }
+            'f' | 'F' => {
+                let arg = next_arg(args).unwrap_or("0");
+                let value = arg.trim().parse::<f64>().unwrap_or_else(|_| {
+                    eprintln!("printf: {}: invalid number", arg);
+                    *status = 1;
+                    0.0
+                });
+                let digits = format!("{:.*}", spec.precision.unwrap_or(6), value.abs());
+                // The precision is the number of decimals here, it doesn't stop zero padding
+                spec.precision = None;
+                number_text(sign(value.is_sign_negative(), &spec), "", &digits, &spec)
+            }
+            other => return Err(format!("%{}: invalid format character", other)),
+        };
+        output.extend(pad(&text, &spec).into_bytes());
+    }
+    Ok(false)
+}
+
+fn sign(negative: bool, spec: &Spec) -> &'static str {
+    if negative {
+        "-"
+    } else if spec.plus {
+        "+"
+    } else if spec.space {
+        " "
+    } else {
+        ""
+    }
+}
+
+// A number for %d, %x and the like, accepting 0x and 0 prefixes and 'c for
+// the code of a character. Invalid numbers count as 0 and fail the command.
+fn integer(arg: &str, status: &mut i32) -> i64 {
+    let s = arg.trim();
+    if let Some(c) = s.strip_prefix(['\'', '"']).and_then(|s| s.chars().next()) {
+        return c as i64;
+    }
+
+    let (negative, digits) = match s.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, s.strip_prefix('+').unwrap_or(s)),
+    };
+    let parsed = if let Some(hex) = digits
+        .strip_prefix("0x")
+        .or_else(|| digits.strip_prefix("0X"))
+    {
+        i64::from_str_radix(hex, 16)
+    } else if digits.len() > 1 && digits.starts_with('0') {
+        i64::from_str_radix(&digits[1..], 8)
+    } else {
+        digits.parse()
+    };
+
+    match parsed {
+        Ok(value) if negative => value.wrapping_neg(),
+        Ok(value) => value,
+        Err(_) => {
+            eprintln!("printf: {}: invalid number", arg);
+            *status = 1;
+            0
+        }
+    }
+}
+
+// The precision of an integer is its minimum number of digits
+fn min_digits(digits: String, spec: &Spec) -> String {
+    match spec.precision {
+        Some(precision) if digits.len() < precision => {
+            format!("{}{}", "0".repeat(precision - digits.len()), digits)
+        }
+        _ => digits,
+    }
+}
+
+// Zero padding goes between the sign and the digits
+fn number_text(sign: &str, prefix: &str, digits: &str, spec: &Spec) -> String {
+    let len = sign.len() + prefix.len() + digits.len();
+    let zeros = match spec.width {
+        Some(width) if spec.zero && !spec.left && spec.precision.is_none() && width > len => {
+            "0".repeat(width - len)
+        }
+        _ => String::new(),
+    };
+    format!("{}{}{}{}", sign, prefix, zeros, digits)
+}
+
+fn pad(text: &str, spec: &Spec) -> String {
+    let len = text.chars().count();
+    match spec.width {
+        Some(width) if width > len && spec.left => format!("{}{}", text, " ".repeat(width - len)),
+        Some(width) if width > len => format!("{}{}", " ".repeat(width - len), text),
+        _ => text.to_string(),
+    }
+}
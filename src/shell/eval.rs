@@ -1,158 +1,886 @@
-use crate::shell::builtin::handle_builtin;
-use crate::shell::command::{CommandExpr, execute_command, spawn_command};
-use crate::shell::parser::{parse_expr, tokenize};
-use os_pipe::{PipeReader, pipe};
+use crate::shell::arith::let_builtin;
+use crate::shell::builtin::{autocd_target, change_dir, handle_builtin, is_builtin};
+use crate::shell::command::{CommandExpr, RedirectKind, execute_command, spawn_command};
+use crate::shell::condition::extended_test;
+use crate::shell::echo;
+use crate::shell::expand::{
+    expand_heredoc, expand_pattern, expand_string, expand_words, parse_assignment, quote,
+};
+use crate::shell::jobs::{Job, add_job, command_text};
+use crate::shell::notfound::command_not_found;
+use crate::shell::parser::{matching_paren, parse_expr, tokenize};
+use crate::shell::procsub::ProcessSubstitutions;
+use crate::shell::signal::{exit_code, interrupted};
+use crate::shell::state::{Jump, ShellState};
+use crate::shell::time::{POSIX_FORMAT, Timer, wait_child};
+use crate::shell::trap::{run_pending_traps, run_trap};
+use glob::Pattern;
+use os_pipe::{PipeReader, PipeWriter, dup_stdout, pipe};
 use std::collections::HashMap;
-use std::process::Stdio;
+use std::io::{self, Write};
+use std::process::{Child, ExitStatus, Stdio};
+use std::thread::{self, JoinHandle};
 
 pub struct EvalResult {
-    pub success: bool,
+    pub status: i32,
     pub should_exit: bool,
 }
 
-pub fn eval_expr(expr: CommandExpr, aliases: &HashMap<String, String>) -> Option<EvalResult> {
+impl EvalResult {
+    pub fn new(status: i32) -> Self {
+        Self {
+            status,
+            should_exit: false,
+        }
+    }
+
+    pub fn success(&self) -> bool {
+        self.status == 0
+    }
+}
+
+// Status reported for commands that failed to start for other reasons
+pub(crate) const CANNOT_EXECUTE_STATUS: i32 = 126;
+
+fn child_status(status: ExitStatus, state: &mut ShellState) -> i32 {
+    if interrupted(status) {
+        state.interrupted = true;
+    }
+    exit_code(status)
+}
+
+// Report a command that could not be started and return its status
+pub fn spawn_error(args: &[String], err: io::Error, state: &mut ShellState) -> i32 {
+    if err.kind() == io::ErrorKind::NotFound {
+        return command_not_found(args, state);
+    }
+
+    eprintln!("ryn: {}: {}", args[0], err);
+    CANNOT_EXECUTE_STATUS
+}
+
+// Expand the words of a command, printing the result when xtrace is on
+fn prepare_args(words: &[String], state: &mut ShellState) -> Option<Vec<String>> {
+    match expand_words(words, state) {
+        Ok(args) => {
+            if state.options.xtrace && !args.is_empty() {
+                trace(&args);
+            }
+            Some(args)
+        }
+        Err(err) => {
+            eprintln!("{}", err);
+            None
+        }
+    }
+}
+
+fn trace(args: &[String]) {
+    let quoted: Vec<String> = args.iter().map(|arg| quote(arg)).collect();
+    eprintln!("+ {}", quoted.join(" "));
+}
+
+// Run standalone `NAME=value` words
+fn assign(words: &[String], state: &mut ShellState) -> i32 {
+    for word in words {
+        let (name, value) = parse_assignment(word).unwrap();
+        match expand_string(value, state) {
+            Ok(value) => {
+                if state.options.xtrace {
+                    trace(&[format!("{}={}", name, value)]);
+                }
+                state.set_var(name, value);
+            }
+            Err(err) => {
+                eprintln!("{}", err);
+                return 1;
+            }
+        }
+    }
+    0
+}
+
+// Where the output of a pipeline stage goes
+enum StageOutput {
+    Pipe(PipeWriter),
+    Inherit,
+}
+
+// Feed a here-document or here-string to a command's stdin from a thread,
+// so a large body can't fill the pipe before the command starts reading
+fn here_document(
+    kind: &RedirectKind,
+    text: &str,
+    state: &mut ShellState,
+) -> Option<(PipeReader, JoinHandle<()>)> {
+    let expanded = match kind {
+        RedirectKind::HereDoc { expand: true } => expand_heredoc(text, state),
+        RedirectKind::HereString => expand_string(text, state).map(|word| word + "\n"),
+        _ => Ok(text.to_string()),
+    };
+    let body = match expanded {
+        Ok(body) => body,
+        Err(err) => {
+            eprintln!("{}", err);
+            return None;
+        }
+    };
+
+    let (reader, mut writer) = match pipe() {
+        Ok(pipe) => pipe,
+        Err(err) => {
+            eprintln!("ryn: here-document: {}", err);
+            return None;
+        }
+    };
+    let handle = thread::spawn(move || {
+        // The command may exit without reading it all
+        writer.write_all(body.as_bytes()).ok();
+    });
+    Some((reader, handle))
+}
+
+fn run_command(args: &[String], keep_fds: &[i32], state: &mut ShellState) -> EvalResult {
+    // Check if it's a built-in command
+    if let Some(result) = handle_builtin(args, state) {
+        return result;
+    }
+
+    // Execute external command
+    let result = execute_command(args, keep_fds, state);
+    let status = match result {
+        Ok(status) => child_status(status, state),
+        Err(err) => spawn_error(args, err, state),
+    };
+    EvalResult::new(status)
+}
+
+// The expression of an `((expr))` command. Its outer parentheses have to
+// enclose the whole word, and what they enclose is the expression, so
+// `((a)+(b))` is `(a)+(b)`.
+fn arithmetic_command(word: &str) -> Option<String> {
+    if !word.starts_with("((") || !word.ends_with("))") {
+        return None;
+    }
+    let chars: Vec<char> = word.chars().collect();
+    let close = matching_paren(&chars, 0)?;
+    (close == chars.len() - 1).then(|| chars[1..close].iter().collect())
+}
+
+// Expand and run the words of a simple command
+fn run_simple_command(words: &[String], keep_fds: &[i32], state: &mut ShellState) -> EvalResult {
+    // `((expr))` is the same as `let "expr"`
+    if let [word] = words
+        && let Some(expr) = arithmetic_command(word)
+    {
+        return match expand_string(&expr, state) {
+            Ok(expr) => EvalResult::new(let_builtin(&[expr], state)),
+            Err(err) => {
+                eprintln!("{}", err);
+                EvalResult::new(1)
+            }
+        };
+    }
+
+    let Some(args) = prepare_args(words, state) else {
+        return EvalResult::new(1);
+    };
+    if args.is_empty() {
+        return EvalResult::new(0);
+    }
+
+    // `command NAME` bypasses aliases and autocd
+    if args[0] == "command" && args.len() > 1 && args[1] != "-v" {
+        return run_command(&args[1..], keep_fds, state);
+    }
+
+    if let Some(body) = state.functions.get(&args[0]) {
+        return call_function(body.clone(), &args, state);
+    }
+
+    // Change into directories typed as commands
+    if state.config.autocd
+        && let Some(dir) = autocd_target(&args, state)
+    {
+        let status = match change_dir(&dir, state) {
+            Ok(()) => 0,
+            Err(err) => {
+                eprintln!("cd: {}: {}", dir.display(), err);
+                1
+            }
+        };
+        return EvalResult::new(status);
+    }
+
+    run_command(&args, keep_fds, state)
+}
+
+// Deep recursion would overflow the stack before anything else stops it
+const MAX_FUNCTION_DEPTH: usize = 200;
+
+// Run a function with its arguments as the positional parameters, then
+// restore the variables it made local
+pub fn call_function(body: CommandExpr, args: &[String], state: &mut ShellState) -> EvalResult {
+    if state.locals.len() >= MAX_FUNCTION_DEPTH {
+        eprintln!("ryn: {}: maximum function nesting level exceeded", args[0]);
+        return EvalResult::new(1);
+    }
+
+    let positional = std::mem::replace(&mut state.positional, args[1..].to_vec());
+    state.locals.push(HashMap::new());
+
+    let result = eval_expr(body, state).unwrap_or_else(|| EvalResult::new(0));
+
+    for (name, value) in state.locals.pop().unwrap_or_default() {
+        match value {
+            Some(value) => state.set_var(&name, value),
+            None => state.unset_var(&name),
+        }
+    }
+    state.positional = positional;
+    if matches!(state.jump, Some(Jump::Return)) {
+        state.jump = None;
+    }
+
+    result
+}
+
+// What a loop does after running its body once
+enum LoopStep {
+    Next,
+    Stop,
+    Exit(EvalResult),
+}
+
+fn run_loop_body(body: &CommandExpr, status: &mut i32, state: &mut ShellState) -> LoopStep {
+    if let Some(result) = eval_expr(body.clone(), state) {
+        if result.should_exit {
+            return LoopStep::Exit(result);
+        }
+        *status = result.status;
+    }
+    if state.interrupted || state.aborted {
+        return LoopStep::Stop;
+    }
+
+    // break 2, continue 2 and return are passed on to the enclosing loop
+    match state.jump.take() {
+        None => LoopStep::Next,
+        Some(Jump::Break(count)) => {
+            if count > 1 {
+                state.jump = Some(Jump::Break(count - 1));
+            }
+            LoopStep::Stop
+        }
+        Some(Jump::Continue(count)) if count > 1 => {
+            state.jump = Some(Jump::Continue(count - 1));
+            LoopStep::Stop
+        }
+        Some(Jump::Continue(_)) => LoopStep::Next,
+        Some(Jump::Return) => {
+            state.jump = Some(Jump::Return);
+            LoopStep::Stop
+        }
+    }
+}
+
+// case patterns are globs with quoted parts escaped, a pattern that isn't
+// a valid glob matches itself
+fn pattern_matches(pattern: &str, word: &str) -> bool {
+    match Pattern::new(pattern) {
+        Ok(glob) => glob.matches(word),
+        Err(_) => pattern == word,
+    }
+}
+
+pub fn eval_expr(expr: CommandExpr, state: &mut ShellState) -> Option<EvalResult> {
+    // [[ ]] counts as a simple command, like test and [
+    let is_simple_command = matches!(expr, CommandExpr::Command(_) | CommandExpr::Conditional(_));
+    // Compound commands are made of these, traps run around them
+    let is_command = is_simple_command
+        || matches!(
+            expr,
+            CommandExpr::Pipeline(_) | CommandExpr::Redirect { .. }
+        );
+    // DEBUG and ERR aren't inherited by functions
+    let traps_apply = is_command && state.locals.is_empty();
+
+    if traps_apply && let Some(exit) = run_trap("DEBUG", state) {
+        return Some(exit);
+    }
+
+    let result = eval_inner(expr, state);
+    if let Some(result) = &result {
+        state.last_status = result.status;
+        // Pipelines record their own stages
+        if is_simple_command {
+            state.pipestatus = vec![result.status];
+        }
+    }
+
+    if is_command {
+        let failed = result.as_ref().is_some_and(|result| !result.success());
+        if traps_apply
+            && failed
+            && state.condition_depth == 0
+            && let Some(exit) = run_trap("ERR", state)
+        {
+            return Some(exit);
+        }
+        if let Some(exit) = run_pending_traps(state) {
+            return Some(exit);
+        }
+    }
+    result
+}
+
+// Conditions of if, while, && and || don't trigger the ERR trap
+fn eval_condition(expr: CommandExpr, state: &mut ShellState) -> Option<EvalResult> {
+    state.condition_depth += 1;
+    let result = eval_expr(expr, state);
+    state.condition_depth -= 1;
+    result
+}
+
+fn eval_inner(expr: CommandExpr, state: &mut ShellState) -> Option<EvalResult> {
     match expr {
-        CommandExpr::Command(mut args) => {
+        CommandExpr::Command(mut words) => {
+            if words.iter().all(|word| parse_assignment(word).is_some()) {
+                return Some(EvalResult::new(assign(&words, state)));
+            }
+
             // Expand aliases
-            if let Some(alias) = aliases.get(&args[0]) {
+            if let Some(alias) = state.config.aliases.get(&words[0]) {
                 let mut tokens = tokenize(alias).unwrap_or_default();
                 if let Ok(parsed) = parse_expr(&mut tokens) {
-                    if let CommandExpr::Command(expanded_args) = parsed {
-                        args.splice(0..1, expanded_args);
+                    if let CommandExpr::Command(expanded_words) = parsed {
+                        words.splice(0..1, expanded_words);
                     } else {
-                        return eval_expr(parsed, aliases);
+                        return eval_expr(parsed, state);
                     }
                 }
             }
 
-            // Check if it's a built-in command
-            if let Some(builtin) = handle_builtin(&args) {
-                return Some(EvalResult {
-                    success: true,
-                    should_exit: builtin,
-                });
-            }
-
-            // Execute external command
-            let success = execute_command(&args);
-            Some(EvalResult {
-                success,
-                should_exit: false,
-            })
+            let Some(substitutions) = ProcessSubstitutions::start(&mut words, state) else {
+                return Some(EvalResult::new(1));
+            };
+            let result = run_simple_command(&words, &substitutions.fds(), state);
+            substitutions.finish();
+            Some(result)
         }
         CommandExpr::Sequence(exprs) => {
-            let mut success = true;
+            let mut status = 0;
             for expr in exprs {
-                if let Some(EvalResult {
-                    success: s,
-                    should_exit,
-                }) = eval_expr(expr, aliases)
-                {
-                    if !s {
-                        success = false; // If any command fails, the whole sequence is considered failed
+                state.errexit_exempt = false;
+                if let Some(result) = eval_expr(expr, state) {
+                    status = result.status;
+                    if result.should_exit {
+                        return Some(result); // Exit early if any command indicates it
                     }
-                    if should_exit {
-                        return Some(EvalResult {
-                            success: s,
-                            should_exit: true,
-                        }); // Exit early if any command indicates it
+                    // Ctrl-C and unbound variables abandon the rest of the
+                    // line, like in bash, and break or continue the rest of
+                    // the loop body
+                    if state.interrupted || state.aborted || state.jump.is_some() {
+                        break;
+                    }
+                    if state.options.errexit && !result.success() && !state.errexit_exempt {
+                        break;
                     }
                 }
             }
-            Some(EvalResult {
-                success,
-                should_exit: false,
-            })
+            Some(EvalResult::new(status))
         }
         CommandExpr::And(lhs, rhs) => {
-            if let Some(EvalResult {
-                success: false,
-                should_exit: false,
-            }) = eval_expr(*lhs, aliases)
+            if let Some(result) = eval_condition(*lhs, state)
+                && (!result.success() || result.should_exit || state.jump.is_some())
             {
-                return Some(EvalResult {
-                    success: false,
-                    should_exit: false,
-                });
+                // A failed condition doesn't trigger errexit
+                state.errexit_exempt = true;
+                return Some(result);
             }
-            eval_expr(*rhs, aliases)
+            // Only the last command's failure counts from here
+            state.errexit_exempt = false;
+            eval_expr(*rhs, state)
         }
         CommandExpr::Or(lhs, rhs) => {
-            if let Some(EvalResult {
-                success: true,
-                should_exit: false,
-            }) = eval_expr(*lhs, aliases)
+            if let Some(result) = eval_condition(*lhs, state)
+                && (result.success()
+                    || result.should_exit
+                    || state.interrupted
+                    || state.aborted
+                    || state.jump.is_some())
             {
-                return Some(EvalResult {
-                    success: true,
-                    should_exit: false,
-                });
+                return Some(result);
             }
-            eval_expr(*rhs, aliases)
+            state.errexit_exempt = false;
+            eval_expr(*rhs, state)
         }
-        CommandExpr::Pipeline(cmds) => {
-            let mut processes = Vec::new();
-            let mut prev_reader: Option<PipeReader> = None;
-
-            for (i, expr) in cmds.iter().enumerate() {
-                let mut args = if let CommandExpr::Command(args) = expr {
-                    args.clone()
-                } else {
-                    return Some(EvalResult {
-                        success: false,
-                        should_exit: false,
-                    });
+        CommandExpr::Group(body) => eval_expr(*body, state),
+        CommandExpr::Time { pipeline, posix } => {
+            let timer = Timer::start(state);
+            let result = eval_expr(*pipeline, state);
+            let format = if posix {
+                POSIX_FORMAT.to_string()
+            } else {
+                state.config.time_format.clone()
+            };
+            eprintln!("{}", timer.finish(&format, state));
+            result
+        }
+        CommandExpr::Conditional(words) => Some(EvalResult::new(extended_test(&words, state))),
+        CommandExpr::Function { name, body } => {
+            state.functions.insert(name, *body);
+            Some(EvalResult::new(0))
+        }
+        CommandExpr::If {
+            branches,
+            otherwise,
+        } => {
+            for (condition, body) in branches {
+                let result = eval_condition(condition, state)?;
+                if result.should_exit || state.interrupted || state.aborted || state.jump.is_some()
+                {
+                    return Some(result);
+                }
+                if result.success() {
+                    return eval_expr(body, state);
+                }
+            }
+            match otherwise {
+                Some(body) => eval_expr(*body, state),
+                None => Some(EvalResult::new(0)),
+            }
+        }
+        CommandExpr::While {
+            condition,
+            body,
+            until,
+        } => {
+            state.loop_depth += 1;
+            let mut status = 0;
+            let exit = loop {
+                let Some(result) = eval_condition((*condition).clone(), state) else {
+                    break None;
                 };
+                if result.should_exit {
+                    break Some(result);
+                }
+                if state.interrupted || state.aborted || result.success() == until {
+                    break None;
+                }
+                match run_loop_body(&body, &mut status, state) {
+                    LoopStep::Next => {}
+                    LoopStep::Stop => break None,
+                    LoopStep::Exit(result) => break Some(result),
+                }
+            };
+            state.loop_depth -= 1;
+            Some(exit.unwrap_or(EvalResult::new(status)))
+        }
+        CommandExpr::For { name, words, body } => {
+            // Without `in` the loop goes over the positional parameters
+            let values = match words {
+                Some(words) => match expand_words(&words, state) {
+                    Ok(values) => values,
+                    Err(err) => {
+                        eprintln!("{}", err);
+                        return Some(EvalResult::new(1));
+                    }
+                },
+                None => state.positional.clone(),
+            };
+
+            state.loop_depth += 1;
+            let mut status = 0;
+            let mut exit = None;
+            for value in values {
+                state.set_var(&name, value);
+                match run_loop_body(&body, &mut status, state) {
+                    LoopStep::Next => {}
+                    LoopStep::Stop => break,
+                    LoopStep::Exit(result) => {
+                        exit = Some(result);
+                        break;
+                    }
+                }
+            }
+            state.loop_depth -= 1;
+            Some(exit.unwrap_or(EvalResult::new(status)))
+        }
+        CommandExpr::Case { word, arms } => {
+            let word = match expand_string(&word, state) {
+                Ok(word) => word,
+                Err(err) => {
+                    eprintln!("{}", err);
+                    return Some(EvalResult::new(1));
+                }
+            };
 
-                // Expand aliases
-                if let Some(alias) = aliases.get(&args[0]) {
-                    let mut tokens = tokenize(alias).unwrap_or_default();
-                    if let Ok(parsed) = parse_expr(&mut tokens) {
-                        if let CommandExpr::Command(expanded_args) = parsed {
-                            args.splice(0..1, expanded_args);
-                        } else {
-                            return eval_expr(parsed, aliases);
+            for (patterns, body) in arms {
+                for pattern in patterns {
+                    let pattern = match expand_pattern(&pattern, state) {
+                        Ok(pattern) => pattern,
+                        Err(err) => {
+                            eprintln!("{}", err);
+                            return Some(EvalResult::new(1));
                         }
+                    };
+                    if pattern_matches(&pattern, &word) {
+                        return match body {
+                            Some(body) => eval_expr(body, state),
+                            None => Some(EvalResult::new(0)),
+                        };
                     }
                 }
+            }
+            Some(EvalResult::new(0))
+        }
+        // Redirections are applied by the pipeline code, even for a lone command
+        redirect @ CommandExpr::Redirect { .. } => {
+            eval_inner(CommandExpr::Pipeline(vec![redirect]), state)
+        }
+        CommandExpr::Pipeline(cmds) => run_pipeline(&cmds, false, state),
+        CommandExpr::Background(expr) => match *expr {
+            CommandExpr::Pipeline(cmds) => run_pipeline(&cmds, true, state),
+            command @ (CommandExpr::Command(_) | CommandExpr::Redirect { .. }) => {
+                run_pipeline(&[command], true, state)
+            }
+            _ => {
+                eprintln!("ryn: compound commands can't run in the background");
+                Some(EvalResult::new(1))
+            }
+        },
+    }
+}
 
-                let stdin = if let Some(reader) = prev_reader.take() {
-                    Stdio::from(reader)
-                } else {
-                    Stdio::inherit()
-                };
+// Runs a builtin with its output going into a buffer instead of the shell's
+// stdout, so a pipeline stage can pass it on the way it does echo's
+#[cfg(unix)]
+fn capture_builtin(
+    args: &[String],
+    stdin: Option<&PipeReader>,
+    stderr_to_stdout: bool,
+    state: &mut ShellState,
+) -> (Vec<u8>, i32) {
+    use std::io::Read;
+    use std::os::unix::io::AsRawFd;
+
+    let (mut reader, writer) = match pipe() {
+        Ok(pipe) => pipe,
+        Err(err) => {
+            eprintln!("ryn: {}: {}", args[0], err);
+            return (Vec::new(), 1);
+        }
+    };
+    // Read as it is written, a full pipe would block the builtin
+    let reading = thread::spawn(move || {
+        let mut output = Vec::new();
+        reader.read_to_end(&mut output).ok();
+        output
+    });
+
+    let fds: &[i32] = if stderr_to_stdout { &[1, 2] } else { &[1] };
+    io::stdout().flush().ok();
+    io::stderr().flush().ok();
+    let saved = match redirect_fds(writer.as_raw_fd(), fds) {
+        Ok(saved) => saved,
+        Err(err) => {
+            eprintln!("ryn: {}: {}", args[0], err);
+            return (Vec::new(), 1);
+        }
+    };
+    drop(writer);
+
+    let status = run_builtin(args, stdin, state);
+    io::stdout().flush().ok();
+    io::stderr().flush().ok();
+    // Putting the descriptors back closes the pipe, which ends the reading
+    restore_fds(&saved);
+    (reading.join().unwrap_or_default(), status)
+}
 
-                let stdout = if i < cmds.len() - 1 {
-                    let (reader, writer) = pipe().unwrap();
-                    prev_reader = Some(reader);
-                    Stdio::from(writer)
+// Runs a builtin with the stage's input on fd 0, where a command started in
+// its place would find it
+#[cfg(unix)]
+fn run_builtin(args: &[String], stdin: Option<&PipeReader>, state: &mut ShellState) -> i32 {
+    use std::os::unix::io::AsRawFd;
+
+    let Some(stdin) = stdin else {
+        return handle_builtin(args, state).map_or(1, |result| result.status);
+    };
+    let saved = match redirect_fds(stdin.as_raw_fd(), &[0]) {
+        Ok(saved) => saved,
+        Err(err) => {
+            eprintln!("ryn: {}: {}", args[0], err);
+            return 1;
+        }
+    };
+    let redirected = std::mem::replace(&mut state.stdin_redirected, true);
+    let status = handle_builtin(args, state).map_or(1, |result| result.status);
+    state.stdin_redirected = redirected;
+    restore_fds(&saved);
+    status
+}
+
+#[cfg(not(unix))]
+fn run_builtin(args: &[String], stdin: Option<&PipeReader>, state: &mut ShellState) -> i32 {
+    if stdin.is_some() {
+        eprintln!(
+            "ryn: {}: builtins can't read a pipe on this platform",
+            args[0]
+        );
+        return 1;
+    }
+    handle_builtin(args, state).map_or(1, |result| result.status)
+}
+
+// Points each of fds at target, returning copies of the originals for restore_fds
+#[cfg(unix)]
+fn redirect_fds(target: i32, fds: &[i32]) -> io::Result<Vec<(i32, i32)>> {
+    let mut saved = Vec::new();
+    for &fd in fds {
+        // SAFETY: dup and dup2 only work on descriptor numbers
+        let copy = unsafe { libc::dup(fd) };
+        if copy == -1 || unsafe { libc::dup2(target, fd) } == -1 {
+            let err = io::Error::last_os_error();
+            if copy != -1 {
+                // SAFETY: copy was just duplicated and isn't used anywhere
+                unsafe { libc::close(copy) };
+            }
+            restore_fds(&saved);
+            return Err(err);
+        }
+        saved.push((fd, copy));
+    }
+    Ok(saved)
+}
+
+#[cfg(unix)]
+fn restore_fds(saved: &[(i32, i32)]) {
+    for &(fd, copy) in saved {
+        // SAFETY: copy is a descriptor this shell duplicated and owns
+        unsafe {
+            libc::dup2(copy, fd);
+            libc::close(copy);
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn capture_builtin(
+    args: &[String],
+    _stdin: Option<&PipeReader>,
+    _stderr_to_stdout: bool,
+    _state: &mut ShellState,
+) -> (Vec<u8>, i32) {
+    eprintln!(
+        "ryn: {}: builtins can only end a pipeline on this platform",
+        args[0]
+    );
+    (Vec::new(), 1)
+}
+
+// Run the stages of a pipeline at the same time, connected by pipes.
+// In the background they become a job instead of being waited for.
+fn run_pipeline(
+    cmds: &[CommandExpr],
+    background: bool,
+    state: &mut ShellState,
+) -> Option<EvalResult> {
+    let mut processes: Vec<(usize, Child)> = Vec::new();
+    let mut prev_reader: Option<PipeReader> = None;
+    let mut statuses = vec![0; cmds.len()];
+    let mut writers = Vec::new();
+    let mut substitutions = Vec::new();
+
+    for (i, expr) in cmds.iter().enumerate() {
+        // Peel the redirections off the command
+        let mut command = expr;
+        let mut stderr_to_stdout = false;
+        let mut input = None;
+        while let CommandExpr::Redirect {
+            command: inner,
+            kind,
+            target,
+        } = command
+        {
+            match kind {
+                RedirectKind::Duplicate(2) if target == "1" => stderr_to_stdout = true,
+                RedirectKind::Duplicate(_) => {}
+                RedirectKind::HereDoc { .. } | RedirectKind::HereString => {
+                    input = Some((kind, target));
+                }
+            }
+            command = inner.as_ref();
+        }
+
+        let mut words = if let CommandExpr::Command(words) = command {
+            words.clone()
+        } else {
+            eprintln!("ryn: compound commands can't be used in pipelines");
+            return Some(EvalResult::new(1));
+        };
+
+        // Expand aliases
+        if let Some(alias) = state.config.aliases.get(&words[0]) {
+            let mut tokens = tokenize(alias).unwrap_or_default();
+            if let Ok(parsed) = parse_expr(&mut tokens) {
+                if let CommandExpr::Command(expanded_words) = parsed {
+                    words.splice(0..1, expanded_words);
                 } else {
-                    Stdio::inherit()
-                };
+                    return eval_expr(parsed, state);
+                }
+            }
+        }
 
-                match spawn_command(&args, stdin, stdout, None) {
-                    Ok(child) => processes.push(child),
-                    Err(err) => {
-                        eprintln!("Failed to spawn command '{}': {}", args[0], err);
-                        return Some(EvalResult {
-                            success: false,
-                            should_exit: false,
-                        });
-                    }
+        let stdin = if let Some((kind, text)) = input {
+            prev_reader = None;
+            match here_document(kind, text, state) {
+                Some((reader, writer)) => {
+                    writers.push(writer);
+                    Some(reader)
+                }
+                None => {
+                    statuses[i] = 1;
+                    continue;
                 }
             }
+        } else {
+            prev_reader.take()
+        };
+
+        // The same pipe writer is shared with stderr for 2>&1
+        let (stdout, stderr) = if i < cmds.len() - 1 {
+            let (reader, writer) = pipe().unwrap();
+            prev_reader = Some(reader);
+            let stderr = stderr_to_stdout
+                .then(|| writer.try_clone().ok().map(Stdio::from))
+                .flatten();
+            (StageOutput::Pipe(writer), stderr)
+        } else {
+            let stderr = stderr_to_stdout
+                .then(|| dup_stdout().ok().map(Stdio::from))
+                .flatten();
+            (StageOutput::Inherit, stderr)
+        };
 
-            let mut success = true;
-            for mut child in processes {
-                if !child.wait().map(|s| s.success()).unwrap_or(false) {
-                    success = false;
+        let Some(stage_substitutions) = ProcessSubstitutions::start(&mut words, state) else {
+            statuses[i] = 1;
+            continue;
+        };
+        let keep_fds = stage_substitutions.fds();
+        substitutions.push(stage_substitutions);
+
+        // Dropping the pipe ends lets the neighbours see end of file
+        let args = match prepare_args(&words, state) {
+            Some(args) if !args.is_empty() => args,
+            _ => {
+                statuses[i] = 1;
+                continue;
+            }
+        };
+        if state.functions.contains_key(&args[0]) {
+            eprintln!("ryn: {}: functions can't be used in pipelines", args[0]);
+            statuses[i] = 1;
+            continue;
+        }
+
+        // Builtins run in the shell. echo and printf build their output up
+        // front, the others have their output captured when it goes to a pipe.
+        if matches!(args[0].as_str(), "exit" | "exec") {
+            eprintln!("ryn: {}: can't be used in pipelines", args[0]);
+            statuses[i] = 1;
+            continue;
+        }
+        let output = match (echo::output(&args), &stdout) {
+            (Some(output), _) => Some(output),
+            (None, StageOutput::Pipe(_)) if is_builtin(&args[0]) => Some(capture_builtin(
+                &args,
+                stdin.as_ref(),
+                stderr_to_stdout,
+                state,
+            )),
+            (None, StageOutput::Inherit) if is_builtin(&args[0]) => {
+                statuses[i] = run_builtin(&args, stdin.as_ref(), state);
+                continue;
+            }
+            (None, _) => None,
+        };
+        if let Some((output, status)) = output {
+            statuses[i] = status;
+            match stdout {
+                StageOutput::Pipe(mut writer) => writers.push(thread::spawn(move || {
+                    writer.write_all(&output).ok();
+                })),
+                StageOutput::Inherit => {
+                    io::stdout().write_all(&output).ok();
+                    io::stdout().flush().ok();
                 }
             }
+            continue;
+        }
 
-            Some(EvalResult {
-                success,
-                should_exit: false,
-            })
+        let stdout = match stdout {
+            StageOutput::Pipe(writer) => Stdio::from(writer),
+            StageOutput::Inherit => Stdio::inherit(),
+        };
+        // The first process of a job starts its process group, the others join it
+        let process_group =
+            background.then(|| processes.first().map_or(0, |(_, child)| child.id()));
+        let spawned = spawn_command(
+            &args,
+            stdin.map_or_else(Stdio::inherit, Stdio::from),
+            stdout,
+            stderr,
+            &keep_fds,
+            process_group,
+            state,
+        );
+        // A stage that fails to start doesn't stop the others,
+        // its readers simply see the end of the pipe
+        match spawned {
+            Ok(child) => processes.push((i, child)),
+            Err(err) => statuses[i] = spawn_error(&args, err, state),
         }
     }
+
+    if background {
+        let job = Job::new(
+            command_text(cmds),
+            processes,
+            statuses,
+            writers,
+            substitutions,
+            state.options.pipefail,
+        );
+        add_job(job, state);
+        return Some(EvalResult::new(0));
+    }
+
+    for (i, mut child) in processes {
+        statuses[i] = match wait_child(&mut child, state) {
+            Ok(status) => child_status(status, state),
+            Err(_) => 1,
+        };
+    }
+    for writer in writers {
+        writer.join().ok();
+    }
+    for stage_substitutions in substitutions {
+        stage_substitutions.finish();
+    }
+
+    // With pipefail the rightmost failure wins, otherwise the last command
+    let status = if state.options.pipefail {
+        statuses
+            .iter()
+            .rev()
+            .find(|&&s| s != 0)
+            .copied()
+            .unwrap_or(0)
+    } else {
+        statuses.last().copied().unwrap_or(0)
+    };
+    state.pipestatus = statuses;
+    Some(EvalResult::new(status))
 }
@@ -0,0 +1,149 @@
+use crate::shell::command::exec_command;
+use crate::shell::eval::{CANNOT_EXECUTE_STATUS, EvalResult};
+use crate::shell::notfound::NOT_FOUND_STATUS;
+use crate::shell::state::ShellState;
+
+// What a redirection does to its file descriptor
+#[cfg_attr(not(unix), allow(dead_code))]
+enum Action {
+    Open(String, Mode),
+    Duplicate(i32), // n>&m
+    Close,          // n>&-
+}
+
+#[cfg_attr(not(unix), allow(dead_code))]
+enum Mode {
+    Read,      // <
+    Write,     // > and >|
+    Append,    // >>
+    ReadWrite, // <>
+}
+
+// Parses a redirection word like 2>/tmp/log, >>out or 3<&0. The target can
+// also be the next word, as in `2> /tmp/log`. Returns the fd, what to do
+// with it and how many words were used.
+fn parse_redirection(word: &str, next: Option<&String>) -> Option<(i32, Action, usize)> {
+    let digits = word.chars().take_while(char::is_ascii_digit).count();
+    let rest = &word[digits..];
+
+    let (op, target) = [">>", ">&", "<&", "<>", ">|", ">", "<"]
+        .iter()
+        .find_map(|op| rest.strip_prefix(op).map(|target| (*op, target)))?;
+    let fd = match &word[..digits] {
+        "" if op.starts_with('<') => 0,
+        "" => 1,
+        fd => fd.parse().ok()?,
+    };
+    let (target, used) = match target {
+        "" => (next?.as_str(), 2),
+        target => (target, 1),
+    };
+
+    let action = match op {
+        ">&" | "<&" if target == "-" => Action::Close,
+        ">&" | "<&" => Action::Duplicate(target.parse().ok()?),
+        ">>" => Action::Open(target.to_string(), Mode::Append),
+        "<>" => Action::Open(target.to_string(), Mode::ReadWrite),
+        "<" => Action::Open(target.to_string(), Mode::Read),
+        _ => Action::Open(target.to_string(), Mode::Write),
+    };
+    Some((fd, action, used))
+}
+
+// exec [redirection...] [command [arg...]]. Redirections change the shell's
+// own file descriptors for good. The command replaces the shell, which the
+// REPL does once it has saved the history.
+pub fn exec(args: &[String], state: &mut ShellState) -> EvalResult {
+    let mut i = 0;
+    while let Some(arg) = args.get(i)
+        && let Some((fd, action, used)) = parse_redirection(arg, args.get(i + 1))
+    {
+        if let Err(err) = redirect(fd, action) {
+            eprintln!("exec: {}: {}", arg, err);
+            return EvalResult::new(1);
+        }
+        i += used;
+    }
+
+    let Some(name) = args.get(i) else {
+        return EvalResult::new(0);
+    };
+    if state.path_cache.borrow_mut().lookup(name).is_none() {
+        eprintln!("exec: {}: not found", name);
+        return EvalResult::new(NOT_FOUND_STATUS);
+    }
+
+    state.exec = Some(args[i..].to_vec());
+    EvalResult {
+        status: 0,
+        should_exit: true,
+    }
+}
+
+#[cfg(unix)]
+fn redirect(fd: i32, action: Action) -> std::io::Result<()> {
+    use std::fs::OpenOptions;
+    use std::io::{self, Write};
+    use std::os::unix::io::AsRawFd;
+
+    // Output already written through the old descriptor goes there first
+    io::stdout().flush().ok();
+    io::stderr().flush().ok();
+
+    let source = match action {
+        Action::Close => {
+            // SAFETY: closing a descriptor number has no memory safety requirements
+            if unsafe { libc::close(fd) } == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            return Ok(());
+        }
+        Action::Duplicate(source) => {
+            // SAFETY: dup2 only works on descriptor numbers
+            if unsafe { libc::dup2(source, fd) } == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            return Ok(());
+        }
+        Action::Open(path, mode) => {
+            let mut options = OpenOptions::new();
+            match mode {
+                Mode::Read => options.read(true),
+                Mode::Write => options.write(true).create(true).truncate(true),
+                Mode::Append => options.append(true).create(true),
+                Mode::ReadWrite => options.read(true).write(true).create(true),
+            };
+            options.open(path)?
+        }
+    };
+
+    // The file is closed once copied to its descriptor
+    // SAFETY: both descriptors are valid, the file's stays open until dropped
+    if unsafe { libc::dup2(source.as_raw_fd(), fd) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn redirect(_fd: i32, _action: Action) -> std::io::Result<()> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "redirections are not supported on this platform",
+    ))
+}
+
+// Replace the shell with the command `exec` asked for. Only returns if that
+// failed, with the status the shell should exit with.
+pub fn replace_shell(args: &[String], state: &mut ShellState) -> i32 {
+    let err = match exec_command(args, state) {
+        Ok(status) => return status,
+        Err(err) => err,
+    };
+    eprintln!("exec: {}: {}", args[0], err);
+    if err.kind() == std::io::ErrorKind::NotFound {
+        NOT_FOUND_STATUS
+    } else {
+        CANNOT_EXECUTE_STATUS
+    }
+}
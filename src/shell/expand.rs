@@ -0,0 +1,425 @@
+use crate::shell::arith::{self, ArithError};
+use crate::shell::parser::matching_paren;
+use crate::shell::state::ShellState;
+use glob::{MatchOptions, Pattern};
+use std::fmt;
+
+#[derive(Debug)]
+pub enum ExpandError {
+    Unbound(String),
+    BadSubstitution(String),
+    Arithmetic(String, ArithError),
+}
+
+impl fmt::Display for ExpandError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExpandError::Unbound(name) => write!(f, "ryn: {}: unbound variable", name),
+            ExpandError::BadSubstitution(word) => write!(f, "ryn: {}: bad substitution", word),
+            ExpandError::Arithmetic(expr, err) => write!(f, "ryn: {}: {}", expr, err),
+        }
+    }
+}
+
+// Builds the fields a word expands to
+struct Expander<'a> {
+    state: &'a mut ShellState,
+    split: bool,
+    fields: Vec<String>,
+    current: String,
+    has_content: bool,    // quoted empty strings still make a field
+    literal_quotes: bool, // here-document bodies keep their quotes
+    pattern: String,      // the field as a glob, with quoted parts escaped
+    has_glob: bool,       // the field has unquoted * ? or [
+    as_pattern: bool,     // make fields of patterns instead of text
+}
+
+impl<'a> Expander<'a> {
+    fn new(state: &'a mut ShellState, split: bool) -> Self {
+        Self {
+            state,
+            split,
+            fields: Vec::new(),
+            current: String::new(),
+            has_content: false,
+            literal_quotes: false,
+            pattern: String::new(),
+            has_glob: false,
+            as_pattern: false,
+        }
+    }
+
+    fn push_literal(&mut self, s: &str) {
+        self.current.push_str(s);
+        self.pattern.push_str(&Pattern::escape(s));
+        self.has_content = true;
+    }
+
+    // Unquoted characters can make the field a glob
+    fn push_unquoted(&mut self, c: char) {
+        self.current.push(c);
+        self.pattern.push(c);
+        if self.split && matches!(c, '*' | '?' | '[') {
+            self.has_glob = true;
+        }
+    }
+
+    fn flush(&mut self) {
+        let field = std::mem::take(&mut self.current);
+        let pattern = std::mem::take(&mut self.pattern);
+
+        // Globs that match nothing are left as they are
+        if std::mem::take(&mut self.has_glob) {
+            let matches = glob_matches(&pattern);
+            if !matches.is_empty() {
+                self.fields.extend(matches);
+                self.has_content = false;
+                return;
+            }
+        }
+
+        if self.has_content || !field.is_empty() {
+            self.fields
+                .push(if self.as_pattern { pattern } else { field });
+        }
+        self.has_content = false;
+    }
+
+    // Unquoted expansion results are split into fields on whitespace
+    fn push_expansion(&mut self, value: &str, quoted: bool) {
+        if quoted || !self.split {
+            self.push_literal(value);
+            return;
+        }
+
+        if value.starts_with(char::is_whitespace) {
+            self.flush();
+        }
+        for (i, piece) in value.split_whitespace().enumerate() {
+            if i > 0 {
+                self.flush();
+            }
+            self.push_literal(piece);
+        }
+        if value.ends_with(char::is_whitespace) {
+            self.flush();
+        }
+    }
+
+    // "$@" is one field per positional parameter, unquoted it is split
+    fn push_positional(&mut self, quoted: bool) {
+        let params = self.state.positional.clone();
+        if !quoted {
+            self.push_expansion(&params.join(" "), false);
+        } else if params.is_empty() {
+            if self.current.is_empty() {
+                self.has_content = false;
+            }
+        } else {
+            for (i, param) in params.iter().enumerate() {
+                if i > 0 {
+                    self.flush();
+                }
+                self.push_literal(param);
+            }
+        }
+    }
+
+    fn expand(mut self, word: &str) -> Result<Vec<String>, ExpandError> {
+        let chars: Vec<char> = word.chars().collect();
+        let mut i = 0;
+        // A here-document body expands as if it were double quoted
+        let mut in_double_quotes = self.literal_quotes;
+
+        // Tilde at the start of an unquoted word
+        if !in_double_quotes
+            && chars.first() == Some(&'~')
+            && matches!(chars.get(1), None | Some('/'))
+            && let Some(home) = self.state.home_dir()
+        {
+            self.push_literal(&home.to_string_lossy());
+            i = 1;
+        }
+
+        while i < chars.len() {
+            let c = chars[i];
+            match c {
+                '\'' if !in_double_quotes => {
+                    let end = chars[i + 1..]
+                        .iter()
+                        .position(|&c| c == '\'')
+                        .map(|p| i + 1 + p)
+                        .unwrap_or(chars.len());
+                    let literal: String = chars[i + 1..end].iter().collect();
+                    self.push_literal(&literal);
+                    i = end;
+                }
+                '"' if !self.literal_quotes => {
+                    // Only the opening quote makes a field, so "$@" can make none
+                    if !in_double_quotes {
+                        self.has_content = true;
+                    }
+                    in_double_quotes = !in_double_quotes;
+                }
+                '$' if chars.get(i + 1) == Some(&'@') => {
+                    self.push_positional(in_double_quotes);
+                    i += 1;
+                }
+                '\\' => match chars.get(i + 1) {
+                    // Inside double quotes only a few characters can be escaped
+                    Some(&next)
+                        if !in_double_quotes
+                            || matches!(next, '$' | '\\' | '`')
+                            || (next == '"' && !self.literal_quotes) =>
+                    {
+                        self.push_literal(&next.to_string());
+                        i += 1;
+                    }
+                    _ => self.push_literal("\\"),
+                },
+                '$' => {
+                    let (value, consumed) = self.parameter(&chars[i + 1..])?;
+                    match value {
+                        Some(value) => self.push_expansion(&value, in_double_quotes),
+                        None => self.push_literal("$"),
+                    }
+                    i += consumed;
+                }
+                _ if in_double_quotes => self.push_literal(&c.to_string()),
+                _ => self.push_unquoted(c),
+            }
+            i += 1;
+        }
+
+        self.flush();
+        Ok(self.fields)
+    }
+
+    // Expand the parameter after a `$`, returning its value and how many
+    // characters it used. None means the `$` was literal.
+    fn parameter(&mut self, chars: &[char]) -> Result<(Option<String>, usize), ExpandError> {
+        let result = self.parameter_value(chars);
+        // With set -u an unbound variable abandons the rest of the line
+        if let Err(ExpandError::Unbound(_) | ExpandError::Arithmetic(_, ArithError::Unbound(_))) =
+            &result
+        {
+            self.state.aborted = true;
+        }
+        result
+    }
+
+    fn parameter_value(&mut self, chars: &[char]) -> Result<(Option<String>, usize), ExpandError> {
+        match chars.first() {
+            Some('(') if chars.get(1) == Some(&'(') => {
+                let end = matching_paren(chars, 0)
+                    .filter(|&end| chars[end - 1] == ')')
+                    .ok_or_else(|| {
+                        let word: String = chars.iter().collect();
+                        ExpandError::BadSubstitution(format!("${}", word))
+                    })?;
+                let inner: String = chars[2..end - 1].iter().collect();
+                Ok((Some(self.arithmetic(&inner)?), end + 1))
+            }
+            Some('{') => {
+                let Some(end) = closing_brace(chars) else {
+                    let word: String = chars.iter().collect();
+                    return Err(ExpandError::BadSubstitution(format!("${}", word)));
+                };
+                let inner: String = chars[1..end].iter().collect();
+                Ok((Some(self.braced(&inner)?), end + 1))
+            }
+            Some(&c) if c.is_ascii_alphabetic() || c == '_' => {
+                let len = chars
+                    .iter()
+                    .take_while(|c| c.is_ascii_alphanumeric() || **c == '_')
+                    .count();
+                let name: String = chars[..len].iter().collect();
+                Ok((Some(self.lookup_checked(&name)?), len))
+            }
+            Some(&c) if c.is_ascii_digit() || matches!(c, '?' | '$' | '!' | '#' | '*') => {
+                Ok((Some(self.lookup_checked(&c.to_string())?), 1))
+            }
+            _ => Ok((None, 0)),
+        }
+    }
+
+    // $((expr)), variables in the expression are expanded first
+    fn arithmetic(&mut self, inner: &str) -> Result<String, ExpandError> {
+        let expr = Expander::new(self.state, false).expand(inner)?.concat();
+        match arith::evaluate(&expr, self.state) {
+            Ok(value) => Ok(value.to_string()),
+            Err(err) => Err(ExpandError::Arithmetic(expr.trim().to_string(), err)),
+        }
+    }
+
+    // ${name}, ${name-default}, ${name:-default} and ${name[index]}
+    fn braced(&mut self, inner: &str) -> Result<String, ExpandError> {
+        if let Some((name, index)) = inner.strip_suffix(']').and_then(|s| s.split_once('[')) {
+            return self.array_element(name, index);
+        }
+
+        // The default is only expanded when it is used
+        let default = match inner.split_once(":-") {
+            Some((name, default)) => {
+                Some((self.state.get_var(name).filter(|v| !v.is_empty()), default))
+            }
+            None => inner
+                .split_once('-')
+                .map(|(name, default)| (self.state.get_var(name), default)),
+        };
+        if let Some((value, default)) = default {
+            return match value {
+                Some(value) => Ok(value),
+                None => Ok(Expander::new(self.state, false).expand(default)?.concat()),
+            };
+        }
+
+        if inner.is_empty() || !inner.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err(ExpandError::BadSubstitution(format!("${{{}}}", inner)));
+        }
+        self.lookup_checked(inner)
+    }
+
+    // ${name[n]} is one element, ${name[@]} and ${name[*]} are all of them
+    fn array_element(&self, name: &str, index: &str) -> Result<String, ExpandError> {
+        let Some(values) = self.state.get_array(name) else {
+            return Err(ExpandError::BadSubstitution(format!(
+                "${{{}[{}]}}",
+                name, index
+            )));
+        };
+
+        if index == "@" || index == "*" {
+            return Ok(values.join(" "));
+        }
+
+        match index.parse::<usize>() {
+            Ok(i) => match values.get(i) {
+                Some(value) => Ok(value.clone()),
+                None if self.state.options.nounset => {
+                    Err(ExpandError::Unbound(format!("{}[{}]", name, index)))
+                }
+                None => Ok(String::new()),
+            },
+            Err(_) => Err(ExpandError::BadSubstitution(format!(
+                "${{{}[{}]}}",
+                name, index
+            ))),
+        }
+    }
+
+    fn lookup_checked(&self, name: &str) -> Result<String, ExpandError> {
+        match self.state.get_var(name) {
+            Some(value) => Ok(value),
+            None if self.state.options.nounset => Err(ExpandError::Unbound(name.to_string())),
+            None => Ok(String::new()),
+        }
+    }
+}
+
+// The index of the `}` closing the `{` that starts chars, past nested ${...}
+fn closing_brace(chars: &[char]) -> Option<usize> {
+    let mut depth = 0;
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => i += 1,
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+// Sorted paths matching a glob, hidden files only if the pattern asks for them
+fn glob_matches(pattern: &str) -> Vec<String> {
+    // The glob crate drops a leading ./ from the paths it returns
+    let (prefix, pattern) = match pattern.strip_prefix("./") {
+        Some(rest) => ("./", rest),
+        None => ("", pattern),
+    };
+
+    let Ok(paths) = glob::glob_with(pattern, MatchOptions::new()) else {
+        return Vec::new();
+    };
+    let mut matches: Vec<String> = paths
+        .filter_map(Result::ok)
+        .map(|path| path.to_string_lossy().into_owned())
+        .filter(|path| !is_hidden_match(pattern, path))
+        .map(|path| format!("{}{}", prefix, path))
+        .collect();
+    matches.sort();
+    matches
+}
+
+// The glob crate's require_literal_leading_dot also hides files from
+// patterns like `.*`, so hidden components are checked here instead
+fn is_hidden_match(pattern: &str, path: &str) -> bool {
+    pattern.split('/').zip(path.split('/')).any(|(glob, name)| {
+        let is_glob = glob.contains(['*', '?', '[']);
+        (name.starts_with('.') && !glob.starts_with('.'))
+            || (is_glob && (name == "." || name == ".."))
+    })
+}
+
+// Expand variables, tildes, quotes and globs in words, splitting unquoted expansions
+pub fn expand_words(words: &[String], state: &mut ShellState) -> Result<Vec<String>, ExpandError> {
+    let mut fields = Vec::new();
+    for word in words {
+        fields.extend(Expander::new(state, true).expand(word)?);
+    }
+    Ok(fields)
+}
+
+// Expand a word into a single string, as for the value of an assignment
+pub fn expand_string(word: &str, state: &mut ShellState) -> Result<String, ExpandError> {
+    Ok(Expander::new(state, false).expand(word)?.concat())
+}
+
+// Expand variables in a here-document body, leaving quotes alone
+pub fn expand_heredoc(body: &str, state: &mut ShellState) -> Result<String, ExpandError> {
+    let mut expander = Expander::new(state, false);
+    expander.literal_quotes = true;
+    Ok(expander.expand(body)?.concat())
+}
+
+// Expand a word into a glob pattern where only unquoted characters are special
+pub fn expand_pattern(word: &str, state: &mut ShellState) -> Result<String, ExpandError> {
+    let mut expander = Expander::new(state, false);
+    expander.as_pattern = true;
+    Ok(expander.expand(word)?.concat())
+}
+
+// Split `NAME=value` if the word is a variable assignment
+pub fn parse_assignment(word: &str) -> Option<(&str, &str)> {
+    let (name, value) = word.split_once('=')?;
+    is_valid_name(name).then_some((name, value))
+}
+
+pub fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+// Quote a string so it expands back to itself
+pub fn quote(s: &str) -> String {
+    let is_plain = !s.is_empty()
+        && s.chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./=:,+@%".contains(c));
+
+    if is_plain {
+        s.to_string()
+    } else {
+        format!("'{}'", s.replace('\'', "'\\''"))
+    }
+}
@@ -0,0 +1,201 @@
+use crate::shell::builtin::change_dir;
+use crate::shell::dirstack::display_path;
+use crate::shell::state::ShellState;
+use std::cell::RefCell;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Once the ranks add up to this, they are aged so old entries fade out
+const MAX_TOTAL_RANK: f64 = 9000.0;
+
+struct Entry {
+    path: PathBuf,
+    rank: f64,
+    last_access: u64,
+}
+
+impl Entry {
+    // Weight the visit count by how recently the directory was used
+    fn frecency(&self, now: u64) -> f64 {
+        let age = now.saturating_sub(self.last_access);
+        if age < 3600 {
+            self.rank * 4.0
+        } else if age < 86400 {
+            self.rank * 2.0
+        } else if age < 604800 {
+            self.rank / 2.0
+        } else {
+            self.rank / 4.0
+        }
+    }
+}
+
+// Shared between the shell state and the line editor helper
+pub type SharedFrecencyDb = Rc<RefCell<FrecencyDb>>;
+
+// Directories visited with cd, stored next to history.txt
+#[derive(Default)]
+pub struct FrecencyDb {
+    path: Option<PathBuf>,
+    entries: Vec<Entry>,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+impl FrecencyDb {
+    pub fn shared() -> SharedFrecencyDb {
+        Rc::new(RefCell::new(Self::load()))
+    }
+
+    fn load() -> Self {
+        let Some(path) = dirs::data_local_dir().map(|p| p.join("ryn").join("frecency.txt")) else {
+            return Self::default();
+        };
+
+        // Each line is `rank|last_access|path`
+        let entries = fs::read_to_string(&path)
+            .unwrap_or_default()
+            .lines()
+            .filter_map(|line| {
+                let mut parts = line.splitn(3, '|');
+                let rank = parts.next()?.parse().ok()?;
+                let last_access = parts.next()?.parse().ok()?;
+                let path = PathBuf::from(parts.next()?);
+                Some(Entry {
+                    path,
+                    rank,
+                    last_access,
+                })
+            })
+            .collect();
+
+        Self {
+            path: Some(path),
+            entries,
+        }
+    }
+
+    fn save(&self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+
+        let contents: String = self
+            .entries
+            .iter()
+            .map(|e| format!("{}|{}|{}\n", e.rank, e.last_access, e.path.display()))
+            .collect();
+
+        if let Err(err) = fs::write(path, contents) {
+            eprintln!("z: could not save {}: {}", path.display(), err);
+        }
+    }
+
+    pub fn record(&mut self, dir: &Path) {
+        let now = now();
+
+        match self.entries.iter_mut().find(|e| e.path == dir) {
+            Some(entry) => {
+                entry.rank += 1.0;
+                entry.last_access = now;
+            }
+            None => self.entries.push(Entry {
+                path: dir.to_path_buf(),
+                rank: 1.0,
+                last_access: now,
+            }),
+        }
+
+        let total: f64 = self.entries.iter().map(|e| e.rank).sum();
+        if total > MAX_TOTAL_RANK {
+            for entry in self.entries.iter_mut() {
+                entry.rank *= 0.99;
+            }
+            self.entries.retain(|e| e.rank >= 1.0);
+        }
+
+        self.save();
+    }
+
+    // Existing directories matching all fragments, best match first
+    pub fn matches(&self, fragments: &[String]) -> Vec<(f64, &Path)> {
+        let now = now();
+        let mut matches: Vec<(f64, &Path)> = self
+            .entries
+            .iter()
+            .filter(|e| matches_fragments(&e.path, fragments) && e.path.is_dir())
+            .map(|e| (e.frecency(now), e.path.as_path()))
+            .collect();
+
+        matches.sort_by(|a, b| b.0.total_cmp(&a.0));
+        matches
+    }
+}
+
+// Fragments must appear in order, and the last one in the final component
+fn matches_fragments(path: &Path, fragments: &[String]) -> bool {
+    let haystack = path.to_string_lossy().to_lowercase();
+    let mut rest = haystack.as_str();
+
+    for fragment in fragments {
+        let fragment = fragment.to_lowercase();
+        match rest.find(&fragment) {
+            Some(i) => rest = &rest[i + fragment.len()..],
+            None => return false,
+        }
+    }
+
+    match fragments.last() {
+        Some(last) => path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_lowercase())
+            .is_some_and(|name| name.contains(&last.to_lowercase())),
+        None => true,
+    }
+}
+
+// A directory given as is, the way completion leaves it, or the best match
+pub fn z_target(args: &[String], db: &FrecencyDb) -> Option<PathBuf> {
+    if let [arg] = args
+        && Path::new(arg).is_dir()
+    {
+        return Some(PathBuf::from(arg));
+    }
+    db.matches(args).first().map(|(_, path)| path.to_path_buf())
+}
+
+pub fn z(args: &[String], state: &mut ShellState) -> i32 {
+    if args.first().map(String::as_str) == Some("-l") {
+        // Lowest score first so the best match ends up next to the prompt
+        for (score, path) in state.frecency.borrow().matches(&args[1..]).iter().rev() {
+            println!("{:<10.1} {}", score, display_path(path));
+        }
+        return 0;
+    }
+
+    let target = if args.is_empty() {
+        state.home_dir()
+    } else {
+        z_target(args, &state.frecency.borrow())
+    };
+
+    let Some(target) = target else {
+        eprintln!("z: no match found");
+        return 1;
+    };
+
+    match change_dir(&target, state) {
+        Ok(()) => 0,
+        Err(err) => {
+            eprintln!("z: {}: {}", target.display(), err);
+            1
+        }
+    }
+}
@@ -0,0 +1,472 @@
+use crate::shell::command::{CommandExpr, RedirectKind};
+use crate::shell::procsub::ProcessSubstitutions;
+use crate::shell::signal::{
+    Disposition, SIGINT, describe, list_signals, pending_signal, set_disposition, signal_name,
+    signal_number, status_code,
+};
+use crate::shell::state::ShellState;
+use std::io::IsTerminal;
+use std::process::Child;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+// How often `wait` checks on its jobs
+const WAIT_INTERVAL: Duration = Duration::from_millis(10);
+
+// Status of `wait` for a process that isn't one of the shell's jobs
+const NOT_A_CHILD_STATUS: i32 = 127;
+
+// A pipeline started with `&`
+pub struct Job {
+    pub id: usize,
+    pub command: String,
+    pub pgid: u32,                        // process group, the pid of its first process
+    processes: Vec<(usize, Child, bool)>, // stage, process and whether it finished
+    statuses: Vec<i32>,                   // exit status of each stage
+    writers: Vec<JoinHandle<()>>,         // here-documents fed to it
+    substitutions: Vec<ProcessSubstitutions>,
+    pipefail: bool,
+}
+
+impl Job {
+    pub fn new(
+        command: String,
+        processes: Vec<(usize, Child)>,
+        statuses: Vec<i32>,
+        writers: Vec<JoinHandle<()>>,
+        substitutions: Vec<ProcessSubstitutions>,
+        pipefail: bool,
+    ) -> Self {
+        Self {
+            id: 0,
+            command,
+            pgid: processes.first().map_or(0, |(_, child)| child.id()),
+            processes: processes
+                .into_iter()
+                .map(|(stage, child)| (stage, child, false))
+                .collect(),
+            statuses,
+            writers,
+            substitutions,
+            pipefail,
+        }
+    }
+
+    pub fn pids(&self) -> impl Iterator<Item = u32> + '_ {
+        self.processes.iter().map(|(_, child, _)| child.id())
+    }
+
+    // Collect the processes that exited, returns true once they all have
+    fn poll(&mut self) -> bool {
+        for (stage, child, done) in &mut self.processes {
+            if *done {
+                continue;
+            }
+            match child.try_wait() {
+                Ok(Some(status)) => self.statuses[*stage] = status_code(status),
+                Ok(None) => continue,
+                Err(_) => self.statuses[*stage] = 1,
+            }
+            *done = true;
+        }
+        self.processes.iter().all(|(_, _, done)| *done)
+    }
+
+    // Status of the job, with the same pipefail rule as foreground pipelines
+    fn status(&self) -> i32 {
+        if self.pipefail {
+            self.statuses
+                .iter()
+                .rev()
+                .find(|&&s| s != 0)
+                .copied()
+                .unwrap_or(0)
+        } else {
+            self.statuses.last().copied().unwrap_or(0)
+        }
+    }
+
+    fn stage_status(&self, pid: u32) -> Option<i32> {
+        self.processes
+            .iter()
+            .find(|(_, child, _)| child.id() == pid)
+            .map(|(stage, _, _)| self.statuses[*stage])
+    }
+
+    // Release what kept the pipeline fed once it is done
+    fn finish(self) {
+        for writer in self.writers {
+            writer.join().ok();
+        }
+        for substitutions in self.substitutions {
+            substitutions.finish();
+        }
+    }
+
+    // Running, Done, Exit 1 or how a signal killed it
+    fn state_text(&self, done: bool) -> String {
+        let status = self.status();
+        match status {
+            _ if !done => "Running".to_string(),
+            0 => "Done".to_string(),
+            128.. => describe(status - 128)
+                .map(String::from)
+                .unwrap_or_else(|| format!("Exit {}", status)),
+            _ => format!("Exit {}", status),
+        }
+    }
+}
+
+// The words of the pipeline, to show in job listings and match %name
+pub fn command_text(stages: &[CommandExpr]) -> String {
+    let texts: Vec<String> = stages.iter().map(stage_text).collect();
+    texts.join(" | ")
+}
+
+fn stage_text(expr: &CommandExpr) -> String {
+    match expr {
+        CommandExpr::Command(words) => words.join(" "),
+        CommandExpr::Redirect {
+            command,
+            kind,
+            target,
+        } => match kind {
+            RedirectKind::Duplicate(fd) => format!("{} {}>&{}", stage_text(command), fd, target),
+            RedirectKind::HereDoc { .. } => format!("{} <<EOF", stage_text(command)),
+            RedirectKind::HereString => format!("{} <<< {}", stage_text(command), target),
+        },
+        _ => String::new(),
+    }
+}
+
+// Add a job to the table, numbered after the highest one
+pub fn add_job(mut job: Job, state: &mut ShellState) {
+    job.id = state.jobs.iter().map(|job| job.id).max().unwrap_or(0) + 1;
+    state.last_background = job.pids().last();
+
+    if std::io::stdin().is_terminal() {
+        eprintln!("[{}] {}", job.id, job.pids().last().unwrap_or(job.pgid));
+    }
+    state.jobs.push(job);
+}
+
+// + marks the current job, the most recent one, and - the one before
+fn marker(index: usize, count: usize) -> char {
+    if index + 1 == count {
+        '+'
+    } else if index + 2 == count {
+        '-'
+    } else {
+        ' '
+    }
+}
+
+fn job_line(job: &Job, marker: char, done: bool, long: bool) -> String {
+    let pgid = if long {
+        format!(" {}", job.pgid)
+    } else {
+        String::new()
+    };
+    format!(
+        "[{}]{}{}  {:<24}{}",
+        job.id,
+        marker,
+        pgid,
+        job.state_text(done),
+        job.command
+    )
+}
+
+// Report the jobs that finished since the last prompt. Scripts keep them
+// until they are waited for, so `wait` can still get their status.
+pub fn notify_finished(state: &mut ShellState) {
+    if !std::io::stdin().is_terminal() {
+        return;
+    }
+    let count = state.jobs.len();
+    let mut index = 0;
+    let mut position = 0;
+    while index < state.jobs.len() {
+        if state.jobs[index].poll() {
+            let job = state.jobs.remove(index);
+            eprintln!("{}", job_line(&job, marker(position, count), true, false));
+            job.finish();
+        } else {
+            index += 1;
+        }
+        position += 1;
+    }
+}
+
+// Index of the job a %spec names: %n, %+, %%, %-, %name or %?substr
+fn find_job(spec: &str, jobs: &[Job]) -> Result<usize, String> {
+    let no_such_job = || "no such job".to_string();
+    let pattern = spec.strip_prefix('%').ok_or_else(no_such_job)?;
+
+    let matching: Vec<usize> = match pattern {
+        "" | "%" | "+" => return jobs.len().checked_sub(1).ok_or_else(no_such_job),
+        "-" => return jobs.len().checked_sub(2).ok_or_else(no_such_job),
+        _ if pattern.chars().all(|c| c.is_ascii_digit()) => {
+            let id = pattern.parse::<usize>().map_err(|_| no_such_job())?;
+            return jobs
+                .iter()
+                .position(|job| job.id == id)
+                .ok_or_else(no_such_job);
+        }
+        _ => match pattern.strip_prefix('?') {
+            Some(substring) => (0..jobs.len())
+                .filter(|&i| jobs[i].command.contains(substring))
+                .collect(),
+            None => (0..jobs.len())
+                .filter(|&i| jobs[i].command.starts_with(pattern))
+                .collect(),
+        },
+    };
+
+    match matching.as_slice() {
+        [index] => Ok(*index),
+        [] => Err(no_such_job()),
+        _ => Err("ambiguous job spec".to_string()),
+    }
+}
+
+// jobs [-lp]
+pub fn jobs(args: &[String], state: &mut ShellState) -> i32 {
+    let long = args.iter().any(|arg| arg == "-l");
+    let pids_only = args.iter().any(|arg| arg == "-p");
+
+    let count = state.jobs.len();
+    let mut done = Vec::new();
+    for (index, job) in state.jobs.iter_mut().enumerate() {
+        let finished = job.poll();
+        if pids_only {
+            println!("{}", job.pgid);
+        } else {
+            println!("{}", job_line(job, marker(index, count), finished, long));
+        }
+        if finished {
+            done.push(job.id);
+        }
+    }
+
+    // Finished jobs are only listed once, as they would be notified
+    if !std::io::stdin().is_terminal() {
+        return 0;
+    }
+    for id in done {
+        if let Some(index) = state.jobs.iter().position(|job| job.id == id) {
+            state.jobs.remove(index).finish();
+        }
+    }
+    0
+}
+
+// wait [%job|pid...]: wait for the given jobs, or all of them, and return
+// the status of the last one
+pub fn wait(args: &[String], state: &mut ShellState) -> i32 {
+    // A job id and the pid whose status to return, if one was given
+    let mut targets = Vec::new();
+    let mut status = 0;
+    for arg in args {
+        let target = if arg.starts_with('%') {
+            find_job(arg, &state.jobs)
+                .map(|index| (state.jobs[index].id, None))
+                .map_err(|err| format!("{}: {}", arg, err))
+        } else {
+            match arg.parse::<u32>() {
+                Ok(pid) => state
+                    .jobs
+                    .iter()
+                    .find(|job| job.pids().any(|p| p == pid))
+                    .map(|job| (job.id, Some(pid)))
+                    .ok_or_else(|| format!("pid {} is not a child of this shell", pid)),
+                Err(_) => Err(format!("{}: not a pid or valid job spec", arg)),
+            }
+        };
+        match target {
+            Ok(target) => targets.push(target),
+            Err(err) => {
+                eprintln!("wait: {}", err);
+                status = NOT_A_CHILD_STATUS;
+            }
+        }
+    }
+    if args.is_empty() {
+        targets = state.jobs.iter().map(|job| (job.id, None)).collect();
+    }
+
+    // The shell ignores Ctrl-C, but it should still stop waiting
+    let catch_interrupt = !state.traps.contains_key("INT");
+    if catch_interrupt {
+        set_disposition(SIGINT, Disposition::Catch).ok();
+    }
+
+    for (id, pid) in targets {
+        let Some(index) = state.jobs.iter().position(|job| job.id == id) else {
+            continue;
+        };
+
+        // A trapped signal stops the wait, its trap runs right after
+        let interrupted = loop {
+            if state.jobs[index].poll() {
+                break None;
+            }
+            if let Some(signal) = pending_signal() {
+                break Some(signal);
+            }
+            thread::sleep(WAIT_INTERVAL);
+        };
+        if let Some(signal) = interrupted {
+            if signal == SIGINT && catch_interrupt {
+                state.interrupted = true;
+            }
+            status = 128 + signal;
+            break;
+        }
+
+        let job = state.jobs.remove(index);
+        status = match pid {
+            Some(pid) => job.stage_status(pid).unwrap_or(0),
+            None if args.is_empty() => 0,
+            None => job.status(),
+        };
+        job.finish();
+    }
+
+    if catch_interrupt {
+        set_disposition(SIGINT, Disposition::Default).ok();
+    }
+    status
+}
+
+// kill [-s SIG | -SIG] %job|pid..., or kill -l [status...]
+pub fn kill(args: &[String], state: &mut ShellState) -> i32 {
+    let mut signal = signal_number("TERM").unwrap_or(15);
+    let mut rest = args;
+
+    match rest.first().map(String::as_str) {
+        None => {
+            eprintln!("kill: usage: kill [-s sigspec | -sigspec] pid | jobspec ... or kill -l");
+            return 1;
+        }
+        Some("-l" | "-L") => return list(&rest[1..]),
+        Some("-s" | "-n") => {
+            let Some(spec) = rest.get(1) else {
+                eprintln!("kill: {}: option requires an argument", rest[0]);
+                return 1;
+            };
+            match parse_signal(spec) {
+                Some(number) => signal = number,
+                None => {
+                    eprintln!("kill: {}: invalid signal specification", spec);
+                    return 1;
+                }
+            }
+            rest = &rest[2..];
+        }
+        Some("--") => rest = &rest[1..],
+        Some(spec) if spec.starts_with('-') && spec.len() > 1 => {
+            match parse_signal(&spec[1..]) {
+                Some(number) => signal = number,
+                None => {
+                    eprintln!("kill: {}: invalid signal specification", &spec[1..]);
+                    return 1;
+                }
+            }
+            rest = &rest[1..];
+        }
+        _ => {}
+    }
+    if rest.first().is_some_and(|arg| arg == "--") {
+        rest = &rest[1..];
+    }
+
+    let mut status = 0;
+    for target in rest {
+        let result = if target.starts_with('%') {
+            find_job(target, &state.jobs)
+                .and_then(|index| signal_job(&mut state.jobs[index], signal))
+        } else {
+            match target.parse::<i32>() {
+                Ok(pid) => signal_process(pid, signal),
+                Err(_) => Err("arguments must be process or job IDs".to_string()),
+            }
+        };
+        if let Err(err) = result {
+            eprintln!("kill: {}: {}", target, err);
+            status = 1;
+        }
+    }
+    status
+}
+
+// kill -l lists the signals, or names the ones given by number or status
+fn list(args: &[String]) -> i32 {
+    if args.is_empty() {
+        list_signals();
+        return 0;
+    }
+
+    let mut status = 0;
+    for arg in args {
+        let name = match arg.parse::<i32>() {
+            // Exit statuses of killed commands name their signal
+            Ok(number) => {
+                signal_name(if number > 128 { number - 128 } else { number }).map(String::from)
+            }
+            Err(_) => signal_number(arg).map(|number| number.to_string()),
+        };
+        match name {
+            Some(name) => println!("{}", name),
+            None => {
+                eprintln!("kill: {}: invalid signal specification", arg);
+                status = 1;
+            }
+        }
+    }
+    status
+}
+
+fn parse_signal(spec: &str) -> Option<i32> {
+    match spec.parse::<i32>() {
+        Ok(0) => Some(0),
+        Ok(number) => signal_name(number).map(|_| number),
+        Err(_) => signal_number(spec),
+    }
+}
+
+// The whole process group gets the signal, so every stage of the pipeline
+// and what they started do too
+#[cfg(unix)]
+fn signal_job(job: &mut Job, signal: i32) -> Result<(), String> {
+    signal_process(-(job.pgid as i32), signal)?;
+
+    // Stopped jobs only see these once they continue, as in bash
+    if signal == libc::SIGTERM || signal == libc::SIGHUP {
+        signal_process(-(job.pgid as i32), libc::SIGCONT).ok();
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn signal_process(pid: i32, signal: i32) -> Result<(), String> {
+    // SAFETY: kill has no memory safety requirements
+    if unsafe { libc::kill(pid, signal) } == -1 {
+        return Err(std::io::Error::last_os_error().to_string());
+    }
+    Ok(())
+}
+
+// Windows has no signals, the job's processes are terminated instead
+#[cfg(windows)]
+fn signal_job(job: &mut Job, _signal: i32) -> Result<(), String> {
+    for (_, child, _) in &mut job.processes {
+        child.kill().map_err(|err| err.to_string())?;
+    }
+    Ok(())
+}
+
+#[cfg(windows)]
+fn signal_process(_pid: i32, _signal: i32) -> Result<(), String> {
+    Err("only jobs can be killed on Windows".to_string())
+}
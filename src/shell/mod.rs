@@ -1,11 +1,30 @@
+mod arith;
 mod builtin;
 mod command;
 mod completion;
+mod condition;
 mod config;
+mod dirstack;
+mod echo;
 mod eval;
+mod exec;
+mod expand;
+mod frecency;
 mod history;
+mod jobs;
+mod notfound;
+mod options;
 mod parser;
+mod pathcache;
+mod procsub;
 mod prompt;
+mod read;
 mod repl;
+mod signal;
+mod state;
+mod time;
+mod trap;
+mod ulimit;
+mod umask;
 
 pub use repl::run;
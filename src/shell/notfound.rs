@@ -0,0 +1,109 @@
+use crate::shell::builtin::BUILTINS;
+use crate::shell::command::CommandExpr;
+use crate::shell::eval::{call_function, eval_expr};
+use crate::shell::expand::quote;
+use crate::shell::parser::tokenize;
+use crate::shell::state::ShellState;
+use std::collections::BTreeSet;
+
+// Exit status for commands that could not be found, as in other shells
+pub const NOT_FOUND_STATUS: i32 = 127;
+
+const MAX_SUGGESTIONS: usize = 3;
+
+// Name of the function that handles missing commands, like the config key
+const HANDLER_FUNCTION: &str = "command_not_found_handler";
+
+// Run the handler function or the configured handler, or report the command
+// with close matches
+pub fn command_not_found(args: &[String], state: &mut ShellState) -> i32 {
+    // The function gets the missing command and its arguments as $1, $2...
+    if let Some(body) = state.functions.remove(HANDLER_FUNCTION) {
+        let mut handler_args = vec![HANDLER_FUNCTION.to_string()];
+        handler_args.extend_from_slice(args);
+        let status = call_function(body.clone(), &handler_args, state).status;
+
+        // Unless the handler redefined itself
+        state
+            .functions
+            .entry(HANDLER_FUNCTION.to_string())
+            .or_insert(body);
+        return status;
+    }
+
+    // Taken out while it runs so a missing handler can't recurse forever
+    if let Some(handler) = state.config.command_not_found_handler.take() {
+        let mut handler_args = tokenize(&handler).unwrap_or_default();
+        handler_args.extend(args.iter().map(|arg| quote(arg)));
+
+        let status = eval_expr(CommandExpr::Command(handler_args), state)
+            .map(|result| result.status)
+            .unwrap_or(NOT_FOUND_STATUS);
+
+        state.config.command_not_found_handler = Some(handler);
+        return status;
+    }
+
+    eprintln!("ryn: command not found: {}", args[0]);
+
+    let suggestions = suggest(&args[0], state);
+    if !suggestions.is_empty() {
+        eprintln!("ryn: did you mean: {}?", suggestions.join(", "));
+    }
+
+    NOT_FOUND_STATUS
+}
+
+// Known command names within a small edit distance of the typo
+fn suggest(name: &str, state: &ShellState) -> Vec<String> {
+    let mut candidates: BTreeSet<String> = state.path_cache.borrow_mut().executables().clone();
+    candidates.extend(BUILTINS.iter().map(|s| s.to_string()));
+    candidates.extend(state.config.aliases.keys().cloned());
+
+    let max_distance = (name.chars().count() / 3).clamp(1, 3);
+
+    let mut matches: Vec<(usize, String)> = candidates
+        .into_iter()
+        .map(|candidate| (edit_distance(name, &candidate), candidate))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .collect();
+
+    matches.sort();
+    matches
+        .into_iter()
+        .take(MAX_SUGGESTIONS)
+        .map(|(_, candidate)| candidate)
+        .collect()
+}
+
+// Damerau-Levenshtein distance (optimal string alignment), so swapped
+// letters like `gti` count as a single edit
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+
+    let mut rows = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in rows.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in rows[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut distance = (rows[i - 1][j] + 1)
+                .min(rows[i][j - 1] + 1)
+                .min(rows[i - 1][j - 1] + cost);
+
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(rows[i - 2][j - 2] + 1);
+            }
+
+            rows[i][j] = distance;
+        }
+    }
+
+    rows[a.len()][b.len()]
+}
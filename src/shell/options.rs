@@ -0,0 +1,107 @@
+use crate::shell::state::ShellState;
+
+// Behaviour toggled with the set builtin
+#[derive(Default)]
+pub struct ShellOptions {
+    pub errexit: bool,  // -e: stop a sequence when a command fails
+    pub nounset: bool,  // -u: expanding an unset variable stops the line
+    pub xtrace: bool,   // -x: print commands before running them
+    pub pipefail: bool, // a pipeline fails if any of its commands does
+}
+
+// Long option names, with their single letter flag if they have one
+const OPTION_NAMES: [(&str, Option<char>); 4] = [
+    ("errexit", Some('e')),
+    ("nounset", Some('u')),
+    ("xtrace", Some('x')),
+    ("pipefail", None),
+];
+
+impl ShellOptions {
+    fn get_mut(&mut self, name: &str) -> Option<&mut bool> {
+        match name {
+            "errexit" => Some(&mut self.errexit),
+            "nounset" => Some(&mut self.nounset),
+            "xtrace" => Some(&mut self.xtrace),
+            "pipefail" => Some(&mut self.pipefail),
+            _ => None,
+        }
+    }
+
+    fn get(&self, name: &str) -> bool {
+        match name {
+            "errexit" => self.errexit,
+            "nounset" => self.nounset,
+            "xtrace" => self.xtrace,
+            "pipefail" => self.pipefail,
+            _ => false,
+        }
+    }
+}
+
+fn name_for_flag(flag: char) -> Option<&'static str> {
+    OPTION_NAMES
+        .iter()
+        .find(|(_, letter)| *letter == Some(flag))
+        .map(|(name, _)| *name)
+}
+
+// set [-eux] [+eux] [-o option] [+o option]
+pub fn set(args: &[String], state: &mut ShellState) -> i32 {
+    if args.is_empty() {
+        let mut vars: Vec<_> = state.vars.iter().collect();
+        vars.sort();
+        for (name, value) in vars {
+            println!("{}={}", name, value);
+        }
+        return 0;
+    }
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let enable = arg.starts_with('-');
+        if !enable && !arg.starts_with('+') {
+            eprintln!("set: {}: invalid option", arg);
+            return 2;
+        }
+
+        if arg == "-o" || arg == "+o" {
+            let Some(name) = args.next() else {
+                print_options(state, enable);
+                return 0;
+            };
+            match state.options.get_mut(name) {
+                Some(option) => *option = enable,
+                None => {
+                    eprintln!("set: {}: invalid option name", name);
+                    return 2;
+                }
+            }
+            continue;
+        }
+
+        for flag in arg.chars().skip(1) {
+            match name_for_flag(flag).and_then(|name| state.options.get_mut(name)) {
+                Some(option) => *option = enable,
+                None => {
+                    eprintln!("set: {}{}: invalid option", &arg[..1], flag);
+                    return 2;
+                }
+            }
+        }
+    }
+
+    0
+}
+
+// `set -o` lists the options, `set +o` prints them as commands
+fn print_options(state: &ShellState, human: bool) {
+    for (name, _) in OPTION_NAMES {
+        let enabled = state.options.get(name);
+        if human {
+            println!("{:<15} {}", name, if enabled { "on" } else { "off" });
+        } else {
+            println!("set {}o {}", if enabled { '-' } else { '+' }, name);
+        }
+    }
+}
@@ -1,12 +1,16 @@
-use crate::shell::command::CommandExpr;
-use crate::shell::eval::{EvalResult, eval_expr};
-use dirs::home_dir;
-use std::collections::HashMap;
+use crate::shell::command::{CommandExpr, RedirectKind};
+use crate::shell::eval::eval_expr;
+use crate::shell::expand::is_valid_name;
+use crate::shell::state::ShellState;
 use std::fmt;
 
 #[derive(Debug)]
 pub enum ParseError {
     UnexpectedOperator(String),
+    UnexpectedEnd,
+    UnterminatedQuote,
+    UnterminatedHereDoc(String),
+    UnmatchedParen,
 }
 
 impl fmt::Display for ParseError {
@@ -15,33 +19,56 @@ impl fmt::Display for ParseError {
             ParseError::UnexpectedOperator(ref op) => {
                 write!(f, "syntax error: unexpected '{}'", op)
             }
+            ParseError::UnexpectedEnd => write!(f, "syntax error: unexpected end of input"),
+            ParseError::UnterminatedQuote => write!(f, "syntax error: unterminated quote"),
+            ParseError::UnmatchedParen => write!(f, "syntax error: unmatched '('"),
+            ParseError::UnterminatedHereDoc(ref delimiter) => {
+                write!(
+                    f,
+                    "syntax error: here-document not ended by '{}'",
+                    delimiter
+                )
+            }
         }
     }
 }
 
-pub fn parse_and_execute(
-    input: &str,
-    aliases: &HashMap<String, String>,
-) -> Result<bool, ParseError> {
+pub fn parse_and_execute(input: &str, state: &mut ShellState) -> Result<bool, ParseError> {
     if input.trim().is_empty() {
         return Ok(false);
     }
 
     let mut tokens = tokenize(input)?;
+    // Only comments
+    if tokens.iter().all(|token| token == "\n") {
+        return Ok(false);
+    }
 
     let expr = parse_expr(&mut tokens)?;
 
-    if let Some(EvalResult {
-        success: _,
-        should_exit,
-    }) = eval_expr(expr, aliases)
-    {
-        return Ok(should_exit);
+    state.interrupted = false;
+    state.aborted = false;
+    let result = eval_expr(expr, state);
+
+    // Move the prompt off the line where ^C was echoed
+    if state.interrupted {
+        println!();
     }
 
-    Ok(false)
+    Ok(result.is_some_and(|result| result.should_exit))
+}
+
+// Whether the input stops inside a here-document or a compound command,
+// or after an operator, so more lines are needed
+pub fn is_incomplete(input: &str) -> bool {
+    match tokenize(input) {
+        Ok(mut tokens) => matches!(parse_expr(&mut tokens), Err(ParseError::UnexpectedEnd)),
+        Err(err) => matches!(err, ParseError::UnterminatedHereDoc(_)),
+    }
 }
 
+// Split input into words and operators. Quotes and backslashes are kept in
+// the words, they are removed when the words are expanded before running.
 pub fn tokenize(input: &str) -> Result<Vec<String>, ParseError> {
     let mut tokens = Vec::new();
     let mut current = String::new();
@@ -50,20 +77,38 @@ pub fn tokenize(input: &str) -> Result<Vec<String>, ParseError> {
 
     let chars: Vec<char> = input.chars().collect();
     let mut i = 0;
+    let mut heredocs_from = 0; // tokens before this have their bodies
 
     while i < chars.len() {
         let c = chars[i];
 
         match c {
-            '"' => in_double_quotes = !in_double_quotes,
-            '\'' => in_single_quotes = !in_single_quotes,
-            ' ' if !in_double_quotes && !in_single_quotes => {
+            '\'' if !in_double_quotes => {
+                in_single_quotes = !in_single_quotes;
+                current.push(c);
+            }
+            _ if in_single_quotes => current.push(c),
+            '\\' => {
+                current.push(c);
+                if let Some(&next) = chars.get(i + 1) {
+                    current.push(next);
+                    i += 1;
+                }
+            }
+            '"' => {
+                in_double_quotes = !in_double_quotes;
+                current.push(c);
+            }
+            _ if in_double_quotes => current.push(c),
+            ' ' | '\t' => {
                 if !current.is_empty() {
                     tokens.push(current.clone());
                     current.clear();
                 }
             }
-            '&' | '|' if !in_double_quotes && !in_single_quotes => {
+            // Duplicating a descriptor, as in 2>&1, stays in the word
+            '&' if current.ends_with(['<', '>']) => current.push(c),
+            '&' | '|' => {
                 if !current.is_empty() {
                     tokens.push(current.clone());
                     current.clear();
@@ -72,29 +117,75 @@ pub fn tokenize(input: &str) -> Result<Vec<String>, ParseError> {
                 if i + 1 < chars.len() && chars[i + 1] == c {
                     tokens.push(format!("{}{}", c, c));
                     i += 1; // consume both
+                } else if c == '|' && chars.get(i + 1) == Some(&'&') {
+                    tokens.push("|&".to_string());
+                    i += 1;
                 } else {
                     tokens.push(c.to_string());
                 }
             }
-            ';' if !in_double_quotes && !in_single_quotes => {
+            ';' => {
                 if !current.is_empty() {
                     tokens.push(current.clone());
                     current.clear();
                 }
-                tokens.push(c.to_string());
-            }
-            '~' if !in_double_quotes && !in_single_quotes => {
-                // Only expand if at the start of a token
-                if current.is_empty() {
-                    if let Some(home_dir) = home_dir() {
-                        current.push_str(home_dir.to_str().unwrap_or_default());
-                    } else {
-                        return Err(ParseError::UnexpectedOperator(
-                            "could not resolve home directory".to_string(),
-                        ));
-                    }
+
+                // ;; ends a case arm
+                if chars.get(i + 1) == Some(&';') {
+                    tokens.push(";;".to_string());
+                    i += 1;
                 } else {
-                    current.push(c);
+                    tokens.push(c.to_string());
+                }
+            }
+            // Process substitution, $((expr)) and ((expr)) stay a single
+            // word, spaces and all
+            '<' | '>' | '$' if chars.get(i + 1) == Some(&'(') => {
+                let end = matching_paren(&chars, i + 1).ok_or(ParseError::UnmatchedParen)?;
+                current.extend(&chars[i..=end]);
+                i = end;
+            }
+            '(' if current.is_empty() && chars.get(i + 1) == Some(&'(') => {
+                let end = matching_paren(&chars, i).ok_or(ParseError::UnmatchedParen)?;
+                current.extend(&chars[i..=end]);
+                i = end;
+            }
+            '<' if chars.get(i + 1) == Some(&'<') => {
+                if !current.is_empty() {
+                    tokens.push(current.clone());
+                    current.clear();
+                }
+
+                let op = match chars.get(i + 2) {
+                    Some('<') => "<<<",
+                    Some('-') => "<<-",
+                    _ => "<<",
+                };
+                tokens.push(op.to_string());
+                i += op.len() - 1;
+            }
+            '\n' => {
+                if !current.is_empty() {
+                    tokens.push(current.clone());
+                    current.clear();
+                }
+
+                // Here-document bodies start on the line after the command
+                i = read_heredocs(&chars, i + 1, &mut tokens, heredocs_from)?;
+                heredocs_from = tokens.len();
+
+                // A newline ends a command like ; unless the line ends in an operator
+                if tokens.last().is_some_and(|last| {
+                    !["|", "|&", "&&", "||", ";", ";;", "\n"].contains(&last.as_str())
+                }) {
+                    tokens.push("\n".to_string());
+                }
+                continue;
+            }
+            '#' if current.is_empty() => {
+                // Comments run to the end of the line
+                while i + 1 < chars.len() && chars[i + 1] != '\n' {
+                    i += 1;
                 }
             }
             _ => current.push(c),
@@ -103,79 +194,247 @@ pub fn tokenize(input: &str) -> Result<Vec<String>, ParseError> {
         i += 1;
     }
 
+    if in_single_quotes || in_double_quotes {
+        return Err(ParseError::UnterminatedQuote);
+    }
+
     if !current.is_empty() {
         tokens.push(current);
     }
 
+    // A here-document on the last line has no body yet
+    if let Some(delimiter) = pending_heredocs(&tokens, heredocs_from).next() {
+        return Err(ParseError::UnterminatedHereDoc(
+            unquote_delimiter(delimiter).0,
+        ));
+    }
+
     Ok(tokens)
 }
 
-pub fn parse_expr(tokens: &mut Vec<String>) -> Result<CommandExpr, ParseError> {
-    let mut lhs = parse_simple_expr(tokens)?;
+// Index of the `)` closing the `(` at `open`, skipping over quotes
+pub fn matching_paren(chars: &[char], open: usize) -> Option<usize> {
+    let mut depth = 0;
+    let mut quote = None;
+    let mut i = open;
 
-    while let Some(op) = tokens.first() {
-        match op.as_str() {
-            "&&" | "||" => {
-                let op = tokens.remove(0);
-                let rhs = parse_expr(tokens)?;
-                lhs = match op.as_str() {
-                    "&&" => CommandExpr::And(Box::new(lhs), Box::new(rhs)),
-                    "||" => CommandExpr::Or(Box::new(lhs), Box::new(rhs)),
-                    _ => unreachable!(),
-                };
+    while i < chars.len() {
+        let c = chars[i];
+        match (quote, c) {
+            (Some(q), _) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '\'' | '"') => quote = Some(c),
+            (None, '\\') => i += 1,
+            (None, '(') => depth += 1,
+            (None, ')') => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
             }
-            ";" => {
-                tokens.remove(0);
-                let rhs = parse_expr(tokens)?;
-                lhs = CommandExpr::Sequence(vec![lhs, rhs]);
+            _ => {}
+        }
+        i += 1;
+    }
+
+    None
+}
+
+// Delimiters of the here-documents started at or after `from`
+fn pending_heredocs(tokens: &[String], from: usize) -> impl Iterator<Item = &String> {
+    tokens[from..]
+        .windows(2)
+        .filter(|pair| pair[0] == "<<" || pair[0] == "<<-")
+        .map(|pair| &pair[1])
+}
+
+// Quoting any part of the delimiter turns off expansion in the body
+fn unquote_delimiter(delimiter: &str) -> (String, bool) {
+    let unquoted: String = delimiter
+        .chars()
+        .filter(|c| !matches!(c, '\'' | '"' | '\\'))
+        .collect();
+    let quoted = unquoted.len() != delimiter.len();
+    (unquoted, quoted)
+}
+
+// Read the bodies of the here-documents started on the previous line,
+// inserting each one as a token after its delimiter. Returns the index
+// of the first character after the bodies.
+fn read_heredocs(
+    chars: &[char],
+    mut i: usize,
+    tokens: &mut Vec<String>,
+    from: usize,
+) -> Result<usize, ParseError> {
+    let mut pos = from;
+    while pos + 1 < tokens.len() {
+        if tokens[pos] != "<<" && tokens[pos] != "<<-" {
+            pos += 1;
+            continue;
+        }
+
+        let strip_tabs = tokens[pos] == "<<-";
+        let (delimiter, _) = unquote_delimiter(&tokens[pos + 1]);
+        let mut body = String::new();
+
+        loop {
+            if i >= chars.len() {
+                return Err(ParseError::UnterminatedHereDoc(delimiter));
             }
-            _ => break,
+            let end = chars[i..]
+                .iter()
+                .position(|&c| c == '\n')
+                .map(|p| i + p)
+                .unwrap_or(chars.len());
+            let mut line: String = chars[i..end].iter().collect();
+            i = end + 1;
+
+            if strip_tabs {
+                line = line.trim_start_matches('\t').to_string();
+            }
+            if line == delimiter {
+                break;
+            }
+            body.push_str(&line);
+            body.push('\n');
         }
+
+        tokens.insert(pos + 2, body);
+        pos += 3;
     }
 
-    Ok(lhs)
+    Ok(i.min(chars.len()))
 }
 
-fn parse_simple_expr(tokens: &mut Vec<String>) -> Result<CommandExpr, ParseError> {
-    if tokens.is_empty() {
-        return Err(ParseError::UnexpectedOperator("empty".to_string()));
+// A whole command line
+pub fn parse_expr(tokens: &mut Vec<String>) -> Result<CommandExpr, ParseError> {
+    let expr = parse_list(tokens, &[])?;
+    match tokens.first() {
+        Some(token) => Err(ParseError::UnexpectedOperator(token.clone())),
+        None => Ok(expr),
     }
+}
+
+const OPERATORS: [&str; 8] = ["|", "|&", "&&", "||", ";", ";;", "&", "\n"];
 
-    let mut pipeline = Vec::new();
+// Words that end a list inside a compound command
+const RESERVED_WORDS: [&str; 8] = ["then", "elif", "else", "fi", "do", "done", "esac", "}"];
 
-    // First command
-    let mut cmd = Vec::new();
-    while !tokens.is_empty() && !["|", "&&", "||", ";"].contains(&tokens[0].as_str()) {
-        cmd.push(tokens.remove(0));
+fn skip_newlines(tokens: &mut Vec<String>) {
+    while tokens.first().is_some_and(|token| token == "\n") {
+        tokens.remove(0);
     }
+}
 
-    if cmd.is_empty() {
-        return Err(ParseError::UnexpectedOperator(
-            "expected command".to_string(),
-        ));
+fn expect(tokens: &mut Vec<String>, word: &str) -> Result<(), ParseError> {
+    match tokens.first() {
+        Some(token) if token == word => {
+            tokens.remove(0);
+            Ok(())
+        }
+        Some(token) => Err(ParseError::UnexpectedOperator(token.clone())),
+        None => Err(ParseError::UnexpectedEnd),
     }
+}
 
-    pipeline.push(CommandExpr::Command(cmd));
+// Commands separated by ; or newlines, up to one of the `until` words
+fn parse_list(tokens: &mut Vec<String>, until: &[&str]) -> Result<CommandExpr, ParseError> {
+    let mut exprs = Vec::new();
 
-    // If there are pipes, collect all commands in the pipeline
-    while let Some(tok) = tokens.first() {
-        if tok == "|" {
-            tokens.remove(0); // consume the pipe
-            let mut next_cmd = Vec::new();
-            while !tokens.is_empty() && !["|", "&&", "||", ";"].contains(&tokens[0].as_str()) {
-                next_cmd.push(tokens.remove(0));
-            }
+    skip_newlines(tokens);
+    while let Some(token) = tokens.first()
+        && !until.contains(&token.as_str())
+    {
+        let expr = parse_and_or(tokens)?;
 
-            if next_cmd.is_empty() {
-                return Err(ParseError::UnexpectedOperator(
-                    "expected command after |".to_string(),
-                ));
+        match tokens.first().map(String::as_str) {
+            Some("&") => {
+                tokens.remove(0);
+                exprs.push(CommandExpr::Background(Box::new(expr)));
+                skip_newlines(tokens);
+            }
+            Some(";" | "\n") => {
+                exprs.push(expr);
+                tokens.remove(0);
+                skip_newlines(tokens);
+            }
+            _ => {
+                exprs.push(expr);
+                break;
             }
+        }
+    }
+
+    match exprs.len() {
+        0 => match tokens.first() {
+            Some(token) => Err(ParseError::UnexpectedOperator(token.clone())),
+            None => Err(ParseError::UnexpectedEnd),
+        },
+        1 => Ok(exprs.pop().unwrap()),
+        _ => Ok(CommandExpr::Sequence(exprs)),
+    }
+}
+
+// Pipelines joined by && and ||, which bind tighter than ;
+fn parse_and_or(tokens: &mut Vec<String>) -> Result<CommandExpr, ParseError> {
+    let mut lhs = parse_pipeline(tokens)?;
+
+    while let Some(op) = tokens.first() {
+        if op != "&&" && op != "||" {
+            break;
+        }
+        let op = tokens.remove(0);
+        skip_newlines(tokens);
+        let rhs = parse_pipeline(tokens)?;
+        lhs = match op.as_str() {
+            "&&" => CommandExpr::And(Box::new(lhs), Box::new(rhs)),
+            "||" => CommandExpr::Or(Box::new(lhs), Box::new(rhs)),
+            _ => unreachable!(),
+        };
+    }
+
+    Ok(lhs)
+}
+
+fn parse_pipeline(tokens: &mut Vec<String>) -> Result<CommandExpr, ParseError> {
+    // `time` measures the whole pipeline, alone it is an ordinary command
+    if tokens.first().is_some_and(|token| token == "time")
+        && tokens
+            .get(1)
+            .is_some_and(|token| !OPERATORS.contains(&token.as_str()))
+    {
+        tokens.remove(0);
+        let posix = tokens.first().is_some_and(|token| token == "-p");
+        if posix {
+            tokens.remove(0);
+        }
+        return Ok(CommandExpr::Time {
+            pipeline: Box::new(parse_pipeline(tokens)?),
+            posix,
+        });
+    }
 
-            pipeline.push(CommandExpr::Command(next_cmd));
-        } else {
+    let mut pipeline = vec![parse_command(tokens)?];
+
+    // If there are pipes, collect all commands in the pipeline
+    while let Some(tok) = tokens.first() {
+        if tok != "|" && tok != "|&" {
             break;
         }
+
+        // consume the pipe, `a |& b` is `a 2>&1 | b`
+        if tokens.remove(0) == "|&" {
+            let command = pipeline.pop().unwrap();
+            pipeline.push(CommandExpr::Redirect {
+                command: Box::new(command),
+                kind: RedirectKind::Duplicate(2),
+                target: "1".to_string(),
+            });
+        }
+
+        skip_newlines(tokens);
+        pipeline.push(parse_command(tokens)?);
     }
 
     if pipeline.len() == 1 {
@@ -184,3 +443,289 @@ fn parse_simple_expr(tokens: &mut Vec<String>) -> Result<CommandExpr, ParseError
         Ok(CommandExpr::Pipeline(pipeline))
     }
 }
+
+// Reserved words are only recognized where a command starts
+fn parse_command(tokens: &mut Vec<String>) -> Result<CommandExpr, ParseError> {
+    if let Some(name) = tokens
+        .first()
+        .and_then(|token| function_name(token, tokens.get(1)))
+    {
+        tokens.remove(0);
+        return parse_function(name, tokens);
+    }
+
+    match tokens.first().map(String::as_str) {
+        None => Err(ParseError::UnexpectedEnd),
+        Some("if") => parse_if(tokens),
+        Some("while" | "until") => parse_while(tokens),
+        Some("for") => parse_for(tokens),
+        Some("case") => parse_case(tokens),
+        Some("[[") => {
+            // && and || belong to the condition here
+            tokens.remove(0);
+            let mut words = Vec::new();
+            loop {
+                match tokens.first().map(String::as_str) {
+                    Some("]]") => break,
+                    Some("\n") => {
+                        tokens.remove(0);
+                    }
+                    Some(_) => words.push(tokens.remove(0)),
+                    None => return Err(ParseError::UnexpectedEnd),
+                }
+            }
+            tokens.remove(0);
+            Ok(CommandExpr::Conditional(words))
+        }
+        Some("{") => {
+            tokens.remove(0);
+            let body = parse_list(tokens, &["}"])?;
+            expect(tokens, "}")?;
+            Ok(CommandExpr::Group(Box::new(body)))
+        }
+        Some("function") => {
+            tokens.remove(0);
+            let name = match tokens.first() {
+                Some(token) => token.strip_suffix("()").unwrap_or(token).to_string(),
+                None => return Err(ParseError::UnexpectedEnd),
+            };
+            if !is_valid_name(&name) {
+                return Err(ParseError::UnexpectedOperator(tokens.remove(0)));
+            }
+            tokens.remove(0);
+            parse_function(name, tokens)
+        }
+        Some(token) if RESERVED_WORDS.contains(&token) || OPERATORS.contains(&token) => {
+            Err(ParseError::UnexpectedOperator(token.to_string()))
+        }
+        Some(_) => parse_simple_command(tokens),
+    }
+}
+
+// The name being defined by `name() ...` or `name () ...`
+fn function_name(first: &str, second: Option<&String>) -> Option<String> {
+    let name = match first.strip_suffix("()") {
+        Some(name) => name,
+        None if second.is_some_and(|token| token == "()") => first,
+        None => return None,
+    };
+    is_valid_name(name).then(|| name.to_string())
+}
+
+// The body after the name, a compound command such as { list; }
+fn parse_function(name: String, tokens: &mut Vec<String>) -> Result<CommandExpr, ParseError> {
+    if tokens.first().is_some_and(|token| token == "()") {
+        tokens.remove(0);
+    }
+    skip_newlines(tokens);
+
+    let body = match tokens.first().map(String::as_str) {
+        Some("{" | "if" | "while" | "until" | "for" | "case") => parse_command(tokens)?,
+        Some(token) => return Err(ParseError::UnexpectedOperator(token.to_string())),
+        None => return Err(ParseError::UnexpectedEnd),
+    };
+
+    Ok(CommandExpr::Function {
+        name,
+        body: Box::new(body),
+    })
+}
+
+// if list; then list; [elif list; then list;]... [else list;] fi
+fn parse_if(tokens: &mut Vec<String>) -> Result<CommandExpr, ParseError> {
+    let mut branches = Vec::new();
+    let mut otherwise = None;
+
+    tokens.remove(0);
+    loop {
+        let condition = parse_list(tokens, &["then"])?;
+        expect(tokens, "then")?;
+        let body = parse_list(tokens, &["elif", "else", "fi"])?;
+        branches.push((condition, body));
+
+        match tokens.first().map(String::as_str) {
+            Some("elif") => {
+                tokens.remove(0);
+            }
+            Some("else") => {
+                tokens.remove(0);
+                otherwise = Some(Box::new(parse_list(tokens, &["fi"])?));
+                expect(tokens, "fi")?;
+                break;
+            }
+            _ => {
+                expect(tokens, "fi")?;
+                break;
+            }
+        }
+    }
+
+    Ok(CommandExpr::If {
+        branches,
+        otherwise,
+    })
+}
+
+// while list; do list; done, and the same with until
+fn parse_while(tokens: &mut Vec<String>) -> Result<CommandExpr, ParseError> {
+    let until = tokens.remove(0) == "until";
+    let condition = parse_list(tokens, &["do"])?;
+    expect(tokens, "do")?;
+    let body = parse_list(tokens, &["done"])?;
+    expect(tokens, "done")?;
+
+    Ok(CommandExpr::While {
+        condition: Box::new(condition),
+        body: Box::new(body),
+        until,
+    })
+}
+
+// for name [in word...]; do list; done
+fn parse_for(tokens: &mut Vec<String>) -> Result<CommandExpr, ParseError> {
+    tokens.remove(0);
+    let name = match tokens.first() {
+        Some(name) if is_valid_name(name) => tokens.remove(0),
+        Some(token) => return Err(ParseError::UnexpectedOperator(token.clone())),
+        None => return Err(ParseError::UnexpectedEnd),
+    };
+
+    skip_newlines(tokens);
+    let mut words = None;
+    if tokens.first().is_some_and(|token| token == "in") {
+        tokens.remove(0);
+        let mut list = Vec::new();
+        while !tokens.is_empty() && !OPERATORS.contains(&tokens[0].as_str()) {
+            list.push(tokens.remove(0));
+        }
+        words = Some(list);
+    }
+    if tokens.first().is_some_and(|token| token == ";") {
+        tokens.remove(0);
+    }
+    skip_newlines(tokens);
+
+    expect(tokens, "do")?;
+    let body = parse_list(tokens, &["done"])?;
+    expect(tokens, "done")?;
+
+    Ok(CommandExpr::For {
+        name,
+        words,
+        body: Box::new(body),
+    })
+}
+
+// case word in [(]pattern[|pattern]...) list;; ... esac
+fn parse_case(tokens: &mut Vec<String>) -> Result<CommandExpr, ParseError> {
+    tokens.remove(0);
+    let word = match tokens.first() {
+        Some(token) if !OPERATORS.contains(&token.as_str()) => tokens.remove(0),
+        Some(token) => return Err(ParseError::UnexpectedOperator(token.clone())),
+        None => return Err(ParseError::UnexpectedEnd),
+    };
+    skip_newlines(tokens);
+    expect(tokens, "in")?;
+    skip_newlines(tokens);
+
+    let mut arms = Vec::new();
+    while tokens.first().is_some_and(|token| token != "esac") {
+        let patterns = parse_patterns(tokens)?;
+
+        skip_newlines(tokens);
+        let body = match tokens.first().map(String::as_str) {
+            Some(";;" | "esac") => None,
+            _ => Some(parse_list(tokens, &[";;", "esac"])?),
+        };
+        arms.push((patterns, body));
+
+        if tokens.first().is_some_and(|token| token == ";;") {
+            tokens.remove(0);
+        }
+        skip_newlines(tokens);
+    }
+    expect(tokens, "esac")?;
+
+    Ok(CommandExpr::Case { word, arms })
+}
+
+// The patterns of a case arm, up to the closing parenthesis
+fn parse_patterns(tokens: &mut Vec<String>) -> Result<Vec<String>, ParseError> {
+    let mut patterns = Vec::new();
+
+    loop {
+        let Some(mut token) = (!tokens.is_empty()).then(|| tokens.remove(0)) else {
+            return Err(ParseError::UnexpectedEnd);
+        };
+        if patterns.is_empty()
+            && let Some(rest) = token.strip_prefix('(')
+        {
+            token = rest.to_string();
+        }
+
+        match token.as_str() {
+            "|" | "" => continue,
+            ")" => break,
+            _ if OPERATORS.contains(&token.as_str()) => {
+                return Err(ParseError::UnexpectedOperator(token));
+            }
+            _ => {}
+        }
+        if let Some(pattern) = token.strip_suffix(')') {
+            if !pattern.is_empty() {
+                patterns.push(pattern.to_string());
+            }
+            break;
+        }
+        patterns.push(token);
+    }
+
+    if patterns.is_empty() {
+        return Err(ParseError::UnexpectedOperator(")".to_string()));
+    }
+    Ok(patterns)
+}
+
+// A command's words with its redirections
+fn parse_simple_command(tokens: &mut Vec<String>) -> Result<CommandExpr, ParseError> {
+    let mut words = Vec::new();
+    let mut redirects = Vec::new();
+
+    while !tokens.is_empty() && !OPERATORS.contains(&tokens[0].as_str()) {
+        let token = tokens.remove(0);
+        match token.as_str() {
+            "<<" | "<<-" if tokens.len() < 2 => {
+                return Err(ParseError::UnexpectedOperator(token));
+            }
+            "<<" | "<<-" => {
+                // The tokenizer put the body right after the delimiter
+                let (_, quoted) = unquote_delimiter(&tokens.remove(0));
+                let body = tokens.remove(0);
+                redirects.push((RedirectKind::HereDoc { expand: !quoted }, body));
+            }
+            "<<<" => {
+                if tokens.is_empty() || OPERATORS.contains(&tokens[0].as_str()) {
+                    return Err(ParseError::UnexpectedOperator("<<<".to_string()));
+                }
+                redirects.push((RedirectKind::HereString, tokens.remove(0)));
+            }
+            _ => words.push(token),
+        }
+    }
+
+    if words.is_empty() {
+        return Err(ParseError::UnexpectedOperator(
+            "expected command".to_string(),
+        ));
+    }
+
+    let mut command = CommandExpr::Command(words);
+    for (kind, target) in redirects {
+        command = CommandExpr::Redirect {
+            command: Box::new(command),
+            kind,
+            target,
+        };
+    }
+    Ok(command)
+}
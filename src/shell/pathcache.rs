@@ -0,0 +1,113 @@
+use crate::shell::command::{path_executables, resolve_program};
+use std::cell::RefCell;
+use std::collections::{BTreeSet, HashMap};
+use std::env;
+use std::ffi::OsString;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+// Shared between the shell state and the line editor helper
+pub type SharedPathCache = Rc<RefCell<PathCache>>;
+
+// Remembers where commands live so PATH is not searched on every run
+#[derive(Default)]
+pub struct PathCache {
+    path_var: Option<OsString>, // the shell's PATH, commands are searched in it
+    locations: HashMap<String, PathBuf>,
+    executables: Option<BTreeSet<String>>,
+}
+
+impl PathCache {
+    pub fn shared() -> SharedPathCache {
+        Rc::new(RefCell::new(Self {
+            path_var: env::var_os("PATH"),
+            ..Self::default()
+        }))
+    }
+
+    // Drop everything if PATH changed since the cache was filled
+    pub fn set_path(&mut self, path_var: Option<OsString>) {
+        if path_var != self.path_var {
+            self.clear();
+            self.path_var = path_var;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.locations.clear();
+        self.executables = None;
+    }
+
+    pub fn lookup(&mut self, name: &str) -> Option<PathBuf> {
+        // Paths are never cached, they don't depend on PATH
+        if name.contains('/') || name.contains(std::path::MAIN_SEPARATOR) {
+            return resolve_program(name, None);
+        }
+
+        if let Some(path) = self.locations.get(name) {
+            if path.is_file() {
+                return Some(path.clone());
+            }
+            self.locations.remove(name);
+        }
+
+        let path = resolve_program(name, self.path_var.as_deref())?;
+        self.locations.insert(name.to_string(), path.clone());
+        Some(path)
+    }
+
+    // All executable names on PATH, scanned once per PATH value
+    pub fn executables(&mut self) -> &BTreeSet<String> {
+        let path_var = self.path_var.as_deref();
+        self.executables
+            .get_or_insert_with(|| path_executables(path_var))
+    }
+
+    pub fn forget(&mut self, name: &str) -> bool {
+        self.locations.remove(name).is_some()
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = (&String, &PathBuf)> {
+        self.locations.iter()
+    }
+}
+
+// hash [-r] [-d name] [name...]
+pub fn hash(args: &[String], cache: &mut PathCache) -> i32 {
+    match args.first().map(String::as_str) {
+        None => {
+            let mut entries: Vec<_> = cache.entries().collect();
+            if entries.is_empty() {
+                println!("hash: hash table empty");
+                return 0;
+            }
+            entries.sort();
+            for (name, path) in entries {
+                println!("{}={}", name, path.display());
+            }
+        }
+        Some("-r") => cache.clear(),
+        Some("-d") => {
+            let mut status = 0;
+            for name in &args[1..] {
+                if !cache.forget(name) {
+                    eprintln!("hash: {}: not found", name);
+                    status = 1;
+                }
+            }
+            return status;
+        }
+        Some(_) => {
+            let mut status = 0;
+            for name in args {
+                if cache.lookup(name).is_none() {
+                    eprintln!("hash: {}: not found", name);
+                    status = 1;
+                }
+            }
+            return status;
+        }
+    }
+
+    0
+}
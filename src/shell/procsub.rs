@@ -0,0 +1,177 @@
+use crate::shell::command::spawn_command;
+use crate::shell::eval::spawn_error;
+use crate::shell::expand::expand_words;
+use crate::shell::parser::tokenize;
+use crate::shell::state::ShellState;
+use os_pipe::{PipeReader, PipeWriter, pipe};
+use std::process::{Child, Stdio};
+
+// Commands started for <(cmd) and >(cmd) words, with the shell's ends of
+// their pipes. The ends stay open until the main command has finished.
+#[derive(Default)]
+pub struct ProcessSubstitutions {
+    children: Vec<Child>,
+    readers: Vec<PipeReader>,
+    writers: Vec<PipeWriter>,
+}
+
+// The end of the pipe the main command gets as /dev/fd/N
+enum PipeEnd {
+    Reader(PipeReader), // <(cmd), the command writes to it
+    Writer(PipeWriter), // >(cmd), the command reads from it
+}
+
+pub fn is_substitution(word: &str) -> bool {
+    (word.starts_with("<(") || word.starts_with(">(")) && word.ends_with(')')
+}
+
+impl ProcessSubstitutions {
+    // Start the commands of every substitution in the words, replacing them
+    // with /dev/fd paths. None if one of them could not be started.
+    pub fn start(words: &mut [String], state: &mut ShellState) -> Option<Self> {
+        let mut substitutions = Self::default();
+
+        for word in words.iter_mut().filter(|word| is_substitution(word)) {
+            let Some(path) = substitutions.spawn(word, state) else {
+                substitutions.finish();
+                return None;
+            };
+            *word = path;
+        }
+
+        Some(substitutions)
+    }
+
+    #[cfg(unix)]
+    fn spawn(&mut self, word: &str, state: &mut ShellState) -> Option<String> {
+        use std::os::unix::io::AsRawFd;
+
+        let (end, stdin, stdout) = match pipe() {
+            Ok((reader, writer)) if word.starts_with('<') => (
+                PipeEnd::Reader(reader),
+                Stdio::inherit(),
+                Stdio::from(writer),
+            ),
+            Ok((reader, writer)) => (
+                PipeEnd::Writer(writer),
+                Stdio::from(reader),
+                Stdio::inherit(),
+            ),
+            Err(err) => {
+                eprintln!("ryn: {}: {}", word, err);
+                return None;
+            }
+        };
+
+        self.spawn_pipeline(&word[2..word.len() - 1], stdin, stdout, state)?;
+
+        let path = match end {
+            PipeEnd::Reader(reader) => {
+                let path = format!("/dev/fd/{}", reader.as_raw_fd());
+                self.readers.push(reader);
+                path
+            }
+            PipeEnd::Writer(writer) => {
+                let path = format!("/dev/fd/{}", writer.as_raw_fd());
+                self.writers.push(writer);
+                path
+            }
+        };
+        Some(path)
+    }
+
+    #[cfg(not(unix))]
+    fn spawn(&mut self, word: &str, _state: &mut ShellState) -> Option<String> {
+        eprintln!("ryn: {}: process substitution is not supported", word);
+        None
+    }
+
+    // The inner command can be a simple command or a pipeline of them
+    #[cfg_attr(not(unix), allow(dead_code))]
+    fn spawn_pipeline(
+        &mut self,
+        input: &str,
+        stdin: Stdio,
+        stdout: Stdio,
+        state: &mut ShellState,
+    ) -> Option<()> {
+        let tokens = match tokenize(input) {
+            Ok(tokens) => tokens,
+            Err(err) => {
+                eprintln!("ryn: {}", err);
+                return None;
+            }
+        };
+        if let Some(op) = tokens
+            .iter()
+            .find(|token| ["|&", "&&", "||", ";"].contains(&token.as_str()))
+        {
+            eprintln!("ryn: unsupported '{}' in process substitution", op);
+            return None;
+        }
+
+        let stages: Vec<&[String]> = tokens.split(|token| token == "|").collect();
+        let mut stdin = Some(stdin);
+        let mut stdout = Some(stdout);
+
+        for (i, stage) in stages.iter().enumerate() {
+            let args = match expand_words(stage, state) {
+                Ok(args) if !args.is_empty() => args,
+                Ok(_) => {
+                    eprintln!("ryn: empty command in process substitution");
+                    return None;
+                }
+                Err(err) => {
+                    eprintln!("{}", err);
+                    return None;
+                }
+            };
+
+            let (stage_stdin, stage_stdout) = if i < stages.len() - 1 {
+                let (reader, writer) = pipe().ok()?;
+                let stage_stdin = stdin.replace(Stdio::from(reader)).unwrap();
+                (stage_stdin, Stdio::from(writer))
+            } else {
+                (stdin.take().unwrap(), stdout.take().unwrap())
+            };
+
+            let spawned = spawn_command(&args, stage_stdin, stage_stdout, None, &[], None, state);
+            match spawned {
+                Ok(child) => self.children.push(child),
+                Err(err) => {
+                    spawn_error(&args, err, state);
+                    return None;
+                }
+            }
+        }
+
+        Some(())
+    }
+
+    // The file descriptors the main command has to inherit
+    pub fn fds(&self) -> Vec<i32> {
+        #[cfg(unix)]
+        {
+            use std::os::unix::io::AsRawFd;
+
+            self.readers
+                .iter()
+                .map(|reader| reader.as_raw_fd())
+                .chain(self.writers.iter().map(|writer| writer.as_raw_fd()))
+                .collect()
+        }
+
+        #[cfg(not(unix))]
+        Vec::new()
+    }
+
+    // Close the shell's pipe ends so the commands see end of file, then
+    // wait for them. Their statuses are ignored, as in other shells.
+    pub fn finish(mut self) {
+        self.readers.clear();
+        self.writers.clear();
+        for child in &mut self.children {
+            child.wait().ok();
+        }
+    }
+}
@@ -1,3 +1,4 @@
+use crate::shell::state::ShellState;
 use chrono::Local;
 use colored::Colorize;
 use hostname::get as get_hostname;
@@ -7,14 +8,28 @@ use std::env;
 use std::process::Command;
 use std::time::Duration;
 
-pub fn parse_prompt(prompt_string: String, time_taken: Option<Duration>) -> String {
+#[allow(clippy::collapsible_if)]
+pub fn parse_prompt(
+    prompt_string: String,
+    time_taken: Option<Duration>,
+    state: &ShellState,
+) -> String {
     let mut variables = HashMap::new();
 
     let mut output = prompt_string.clone();
 
     // Replace ifnotgit variables
     if prompt_string.contains("ifnotgit") {
-        let valid_keys = ["user", "host", "dir", "time24", "timetaken", "compactdir"];
+        let valid_keys = [
+            "user",
+            "host",
+            "dir",
+            "time24",
+            "timetaken",
+            "compactdir",
+            "dirstack",
+            "pipestatus",
+        ];
 
         for key in valid_keys {
             let placeholder_ifnotgit = format!("{{{} ifnotgit}}", key);
@@ -63,6 +78,24 @@ pub fn parse_prompt(prompt_string: String, time_taken: Option<Duration>) -> Stri
             );
         }
     }
+    if output.contains("{dirstack}") {
+        let depth = state.dir_stack.depth();
+        let depth_str = if depth == 0 {
+            String::new()
+        } else {
+            format!("[{}]", depth).bright_black().to_string()
+        };
+        variables.insert("dirstack", depth_str);
+    }
+    if output.contains("{pipestatus}") {
+        let pipestatus = if state.pipestatus.iter().any(|&s| s != 0) {
+            let statuses: Vec<String> = state.pipestatus.iter().map(i32::to_string).collect();
+            statuses.join("|").red().to_string()
+        } else {
+            String::new()
+        };
+        variables.insert("pipestatus", pipestatus);
+    }
     if output.contains("{git}") {
         let git_info = get_git_info().unwrap_or_default();
         variables.insert("git", git_info);
@@ -0,0 +1,366 @@
+use crate::shell::expand::is_valid_name;
+use crate::shell::state::ShellState;
+use rustyline::DefaultEditor;
+use rustyline::error::ReadlineError;
+use std::io::{self, BufRead, IsTerminal};
+use std::time::{Duration, Instant};
+
+// Status when -t runs out, 128 + SIGALRM like bash
+const TIMEOUT_STATUS: i32 = 142;
+const INTERRUPTED_STATUS: i32 = 130;
+
+#[derive(Default)]
+struct ReadOptions {
+    prompt: Option<String>,
+    silent: bool,
+    raw: bool,
+    timeout: Option<Duration>,
+    redirected: bool, // stdin is the pipe or here-document of the read's stage
+}
+
+// How reading a line ended, the text read so far is kept in every case
+enum Outcome {
+    Line,
+    Eof,
+    Timeout,
+    Interrupted,
+}
+
+// read [-p prompt] [-s] [-r] [-t timeout] [NAME...]
+pub fn read(args: &[String], state: &mut ShellState) -> i32 {
+    let (mut options, names) = match parse_options(args) {
+        Ok(parsed) => parsed,
+        Err(err) => {
+            eprintln!("read: {}", err);
+            return 1;
+        }
+    };
+    if let Some(name) = names.iter().find(|name| !is_valid_name(name)) {
+        eprintln!("read: {}: not a valid identifier", name);
+        return 1;
+    }
+
+    options.redirected = state.stdin_redirected;
+    let deadline = options.timeout.map(|timeout| Instant::now() + timeout);
+    let (mut line, mut outcome) = read_line(&options, options.prompt.as_deref(), deadline);
+
+    // Without -r, a trailing backslash continues the input on the next line
+    while !options.raw && matches!(outcome, Outcome::Line) && ends_with_escape(&line) {
+        line.pop();
+        let (more, more_outcome) = read_line(&options, None, deadline);
+        line.push_str(&more);
+        outcome = more_outcome;
+    }
+
+    if matches!(outcome, Outcome::Interrupted) {
+        state.interrupted = true;
+        return INTERRUPTED_STATUS;
+    }
+
+    let chars = unescape(&line, options.raw);
+    if names.is_empty() {
+        state.set_var("REPLY", chars.iter().map(|&(c, _)| c).collect());
+    } else {
+        let ifs = state.get_var("IFS").unwrap_or_else(|| " \t\n".to_string());
+        for (name, value) in names.iter().zip(split_fields(&chars, names.len(), &ifs)) {
+            state.set_var(name, value);
+        }
+    }
+
+    match outcome {
+        Outcome::Line => 0,
+        Outcome::Timeout => TIMEOUT_STATUS,
+        _ => 1,
+    }
+}
+
+fn parse_options(args: &[String]) -> Result<(ReadOptions, &[String]), String> {
+    let mut options = ReadOptions::default();
+    let mut i = 0;
+
+    while let Some(arg) = args.get(i) {
+        if arg == "--" {
+            i += 1;
+            break;
+        }
+        if !arg.starts_with('-') || arg.len() == 1 {
+            break;
+        }
+        i += 1;
+
+        // Flags can be grouped, as in -rs, and -p and -t take the rest or the next word
+        for (pos, flag) in arg.char_indices().skip(1) {
+            match flag {
+                's' => options.silent = true,
+                'r' => options.raw = true,
+                'p' | 't' => {
+                    let value = match &arg[pos + 1..] {
+                        "" => {
+                            i += 1;
+                            args.get(i - 1)
+                                .cloned()
+                                .ok_or_else(|| format!("-{}: option requires an argument", flag))?
+                        }
+                        rest => rest.to_string(),
+                    };
+                    if flag == 'p' {
+                        options.prompt = Some(value);
+                    } else {
+                        options.timeout = Some(parse_timeout(&value)?);
+                    }
+                    break;
+                }
+                _ => return Err(format!("-{}: invalid option", flag)),
+            }
+        }
+    }
+
+    Ok((options, &args[i..]))
+}
+
+fn parse_timeout(value: &str) -> Result<Duration, String> {
+    value
+        .parse::<f64>()
+        .ok()
+        .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+        .ok_or_else(|| format!("{}: invalid timeout specification", value))
+}
+
+fn read_line(
+    options: &ReadOptions,
+    prompt: Option<&str>,
+    deadline: Option<Instant>,
+) -> (String, Outcome) {
+    if options.redirected {
+        return read_unbuffered();
+    }
+    if !io::stdin().is_terminal() {
+        // Input that isn't a terminal is either there already or at its end
+        return read_plain();
+    }
+    if options.silent || deadline.is_some() {
+        if let Some(prompt) = prompt {
+            eprint!("{}", prompt);
+        }
+        return read_raw(options.silent, deadline);
+    }
+    read_editor(prompt.unwrap_or_default())
+}
+
+fn read_plain() -> (String, Outcome) {
+    let mut line = String::new();
+    match io::stdin().lock().read_line(&mut line) {
+        Ok(_) if line.ends_with('\n') => {
+            line.pop();
+            if line.ends_with('\r') {
+                line.pop();
+            }
+            (line, Outcome::Line)
+        }
+        _ => (line, Outcome::Eof),
+    }
+}
+
+// A redirected stdin is read a byte at a time straight from fd 0. Going
+// through io::stdin() would return what its buffer holds of the shell's own
+// input, and keep what it read past the line once fd 0 is put back.
+#[cfg(unix)]
+fn read_unbuffered() -> (String, Outcome) {
+    let mut bytes = Vec::new();
+    let outcome = loop {
+        let mut byte = 0u8;
+        // SAFETY: reading a single byte into a valid buffer
+        match unsafe { libc::read(libc::STDIN_FILENO, (&mut byte as *mut u8).cast(), 1) } {
+            1 if byte == b'\n' => break Outcome::Line,
+            1 => bytes.push(byte),
+            n if n < 0 && io::Error::last_os_error().kind() == io::ErrorKind::Interrupted => {}
+            _ => break Outcome::Eof,
+        }
+    };
+    if matches!(outcome, Outcome::Line) && bytes.last() == Some(&b'\r') {
+        bytes.pop();
+    }
+    (String::from_utf8_lossy(&bytes).into_owned(), outcome)
+}
+
+#[cfg(not(unix))]
+fn read_unbuffered() -> (String, Outcome) {
+    read_plain()
+}
+
+// The line editor, without the shell's history or completion
+fn read_editor(prompt: &str) -> (String, Outcome) {
+    let result = DefaultEditor::new().and_then(|mut editor| editor.readline(prompt));
+    match result {
+        Ok(line) => (line, Outcome::Line),
+        Err(ReadlineError::Interrupted) => (String::new(), Outcome::Interrupted),
+        Err(ReadlineError::Eof) => (String::new(), Outcome::Eof),
+        Err(err) => {
+            eprintln!("read: {}", err);
+            (String::new(), Outcome::Eof)
+        }
+    }
+}
+
+// Reads keys one at a time with the terminal in raw mode, for hidden input
+// and timeouts. The shell ignores SIGINT, so Ctrl-C is handled here too.
+#[cfg(unix)]
+fn read_raw(silent: bool, deadline: Option<Instant>) -> (String, Outcome) {
+    let fd = libc::STDIN_FILENO;
+
+    // SAFETY: tcgetattr fills the termios struct on success
+    let mut original = unsafe { std::mem::zeroed::<libc::termios>() };
+    if unsafe { libc::tcgetattr(fd, &mut original) } != 0 {
+        return read_plain();
+    }
+    let mut raw = original;
+    raw.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG);
+    raw.c_cc[libc::VMIN] = 1;
+    raw.c_cc[libc::VTIME] = 0;
+    // SAFETY: raw is a valid termios struct based on the current one
+    unsafe { libc::tcsetattr(fd, libc::TCSANOW, &raw) };
+
+    let result = read_keys(fd, silent, deadline);
+
+    // SAFETY: original holds the settings read above
+    unsafe { libc::tcsetattr(fd, libc::TCSANOW, &original) };
+    result
+}
+
+#[cfg(unix)]
+fn read_keys(fd: i32, silent: bool, deadline: Option<Instant>) -> (String, Outcome) {
+    use std::io::Write;
+
+    let mut bytes = Vec::new();
+    let mut stderr = io::stderr();
+    let echo = |stderr: &mut io::Stderr, text: &[u8]| {
+        if !silent {
+            stderr.write_all(text).ok();
+            stderr.flush().ok();
+        }
+    };
+
+    let outcome = loop {
+        if let Some(deadline) = deadline {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let mut poll_fd = libc::pollfd {
+                fd,
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            let timeout = remaining.as_millis().min(i32::MAX as u128) as i32;
+            // SAFETY: poll_fd is a valid pollfd for the duration of the call
+            match unsafe { libc::poll(&mut poll_fd, 1, timeout) } {
+                0 => break Outcome::Timeout,
+                n if n < 0 && io::Error::last_os_error().kind() == io::ErrorKind::Interrupted => {
+                    continue;
+                }
+                n if n < 0 => break Outcome::Eof,
+                _ => {}
+            }
+        }
+
+        let mut byte = 0u8;
+        // SAFETY: reading a single byte into a valid buffer
+        match unsafe { libc::read(fd, (&mut byte as *mut u8).cast(), 1) } {
+            1 => {}
+            n if n < 0 && io::Error::last_os_error().kind() == io::ErrorKind::Interrupted => {
+                continue;
+            }
+            _ => break Outcome::Eof,
+        }
+
+        match byte {
+            b'\r' | b'\n' => break Outcome::Line,
+            0x03 => break Outcome::Interrupted, // Ctrl-C
+            0x04 if bytes.is_empty() => break Outcome::Eof, // Ctrl-D
+            0x7f | 0x08 => {
+                // Drop a whole UTF-8 character
+                while bytes.pop().is_some_and(|b| b & 0xc0 == 0x80) {}
+                echo(&mut stderr, b"\x08 \x08");
+            }
+            byte if byte < 0x20 && byte != b'\t' => {}
+            byte => {
+                bytes.push(byte);
+                echo(&mut stderr, &[byte]);
+            }
+        }
+    };
+
+    if !silent || matches!(outcome, Outcome::Interrupted) {
+        eprintln!();
+    }
+    (String::from_utf8_lossy(&bytes).into_owned(), outcome)
+}
+
+#[cfg(not(unix))]
+fn read_raw(_silent: bool, _deadline: Option<Instant>) -> (String, Outcome) {
+    read_plain()
+}
+
+fn ends_with_escape(line: &str) -> bool {
+    line.chars().rev().take_while(|&c| c == '\\').count() % 2 == 1
+}
+
+// Characters of the line, with whether a backslash escaped them from splitting
+fn unescape(line: &str, raw: bool) -> Vec<(char, bool)> {
+    if raw {
+        return line.chars().map(|c| (c, false)).collect();
+    }
+    let mut chars = Vec::new();
+    let mut iter = line.chars();
+    while let Some(c) = iter.next() {
+        match c {
+            '\\' => {
+                if let Some(escaped) = iter.next() {
+                    chars.push((escaped, true));
+                }
+            }
+            c => chars.push((c, false)),
+        }
+    }
+    chars
+}
+
+// Splits the line into at most `count` fields, the last one gets the rest.
+// IFS whitespace around fields is dropped, other IFS characters separate
+// fields one at a time.
+fn split_fields(chars: &[(char, bool)], count: usize, ifs: &str) -> Vec<String> {
+    let is_separator = |&(c, escaped): &(char, bool)| !escaped && ifs.contains(c);
+    let is_space = |entry: &(char, bool)| is_separator(entry) && entry.0.is_whitespace();
+
+    let mut fields = Vec::new();
+    let mut pos = 0;
+    while pos < chars.len() && is_space(&chars[pos]) {
+        pos += 1;
+    }
+
+    while fields.len() + 1 < count && pos < chars.len() {
+        let start = pos;
+        while pos < chars.len() && !is_separator(&chars[pos]) {
+            pos += 1;
+        }
+        fields.push(chars[start..pos].iter().map(|&(c, _)| c).collect());
+
+        // One separator, with any whitespace around it
+        while pos < chars.len() && is_space(&chars[pos]) {
+            pos += 1;
+        }
+        if pos < chars.len() && is_separator(&chars[pos]) {
+            pos += 1;
+            while pos < chars.len() && is_space(&chars[pos]) {
+                pos += 1;
+            }
+        }
+    }
+
+    let mut end = chars.len();
+    while end > pos && is_space(&chars[end - 1]) {
+        end -= 1;
+    }
+    fields.push(chars[pos..end].iter().map(|&(c, _)| c).collect());
+
+    // Names without a field are set to the empty string
+    fields.resize(count, String::new());
+    fields
+}
@@ -1,10 +1,17 @@
 use crate::shell::completion::CommandHelper;
 use crate::shell::config::load_config;
+use crate::shell::exec::replace_shell;
 use crate::shell::history::{load_history, save_history, setup_history};
-use crate::shell::parser::parse_and_execute;
+use crate::shell::jobs::notify_finished;
+use crate::shell::parser::{is_incomplete, parse_and_execute};
 use crate::shell::prompt::parse_prompt;
-use std::io::{self, Write};
-use std::time::Instant;
+use crate::shell::signal::setup_signal_handlers;
+use crate::shell::state::ShellState;
+use crate::shell::trap::{run_pending_traps, run_trap};
+use humantime::format_duration;
+use std::fs;
+use std::io::{self, IsTerminal, Write};
+use std::time::{Duration, Instant};
 
 use rustyline::Editor;
 use rustyline::config::{ColorMode, Config};
@@ -12,8 +19,55 @@ use rustyline::error::ReadlineError;
 use rustyline::history::FileHistory;
 use std::error::Error;
 
-pub fn run() -> Result<(), Box<dyn Error>> {
-    setup_ctrlc_handler();
+// Commands from ~/.config/ryn/rc run before the first prompt.
+// Returns true if they asked the shell to exit.
+fn run_rc_file(state: &mut ShellState) -> bool {
+    let Some(path) = dirs::config_dir().map(|dir| dir.join("ryn/rc")) else {
+        return false;
+    };
+    let Ok(contents) = fs::read_to_string(&path) else {
+        return false;
+    };
+
+    parse_and_execute(&contents, state).unwrap_or_else(|err| {
+        eprintln!("{}: {}", path.display(), err);
+        false
+    })
+}
+
+// Tell the user a command that ran past notify_after finished, in case they
+// looked away from the terminal
+fn notify_long_command(input: &str, duration: Duration, state: &ShellState) {
+    let Some(threshold) = state.config.notify_after else {
+        return;
+    };
+    if duration < threshold || !io::stdin().is_terminal() || !io::stderr().is_terminal() {
+        return;
+    }
+
+    // Control characters, newlines included, would end the escape sequence
+    let command = input
+        .split(char::is_control)
+        .map(str::trim)
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ");
+    let message = format!(
+        "{} finished with status {} after {}",
+        command,
+        state.last_status,
+        format_duration(Duration::from_secs(duration.as_secs()))
+    );
+
+    let mut stderr = io::stderr();
+    for style in &state.config.notify_style {
+        write!(stderr, "{}", style.to_ansi_code("ryn", &message)).ok();
+    }
+    stderr.flush().ok();
+}
+
+pub fn run() -> Result<i32, Box<dyn Error>> {
+    setup_signal_handlers();
 
     // Setup rustyline
     let config = Config::builder()
@@ -21,37 +75,73 @@ pub fn run() -> Result<(), Box<dyn Error>> {
         .completion_type(rustyline::config::CompletionType::List) // maybe circular in config later
         .build();
 
+    let mut state = ShellState::new(load_config()?);
+    if run_rc_file(&mut state) {
+        if let Some(args) = state.exec.take() {
+            return Ok(replace_shell(&args, &mut state));
+        }
+        run_trap("EXIT", &mut state);
+        return Ok(state.last_status);
+    }
+
     let mut rl = Editor::<CommandHelper, FileHistory>::with_config(config)?;
-    rl.set_helper(Some(CommandHelper::new()));
+    let aliases = state.config.aliases.keys().cloned().collect();
+    rl.set_helper(Some(CommandHelper::new(
+        aliases,
+        state.path_cache.clone(),
+        state.frecency.clone(),
+    )));
 
     let history = setup_history()?;
     load_history(&mut rl, &history)?;
-
-    let config = load_config()?;
-    let prompt_string = config.prompt;
+    let prompt_string = state.config.prompt.clone();
 
     let mut last_duration = None;
 
     loop {
-        let prompt = parse_prompt(prompt_string.clone(), last_duration);
+        // Traps of signals that arrived while waiting for input
+        if run_pending_traps(&mut state).is_some() {
+            break;
+        }
+        notify_finished(&mut state);
+
+        let prompt = parse_prompt(prompt_string.clone(), last_duration, &state);
 
-        print!("{}", config.cursor.to_ansi_code());
+        print!("{}", state.config.cursor.to_ansi_code());
         io::stdout().flush().unwrap();
 
         let readline = rl.readline(&prompt);
 
         match readline {
             Ok(line) => {
-                let line = line.trim();
-                if line.is_empty() {
+                let mut input = line.trim().to_string();
+                if input.is_empty() {
                     continue;
                 }
 
-                rl.add_history_entry(line).ok();
+                // Keep reading until compound commands and here-documents end
+                while is_incomplete(&input) {
+                    match rl.readline("> ") {
+                        Ok(more) => {
+                            input.push('\n');
+                            input.push_str(&more);
+                        }
+                        Err(ReadlineError::Interrupted) => {
+                            input.clear();
+                            break;
+                        }
+                        Err(_) => break,
+                    }
+                }
+                if input.is_empty() {
+                    continue;
+                }
+
+                rl.add_history_entry(&input).ok();
 
                 let start_time = Instant::now();
 
-                match parse_and_execute(line, &config.aliases) {
+                match parse_and_execute(&input, &mut state) {
                     Ok(value) => {
                         if value {
                             break;
@@ -63,7 +153,9 @@ pub fn run() -> Result<(), Box<dyn Error>> {
                     }
                 }
 
-                last_duration = Some(start_time.elapsed());
+                let duration = start_time.elapsed();
+                notify_long_command(&input, duration, &state);
+                last_duration = Some(duration);
             }
             Err(ReadlineError::Interrupted) => continue,
             Err(ReadlineError::Eof) => {
@@ -77,13 +169,13 @@ pub fn run() -> Result<(), Box<dyn Error>> {
         }
     }
 
-    save_history(&mut rl, &history)?;
-    Ok(())
-}
+    // exec saves the history first, the process is gone after that
+    if let Some(args) = state.exec.take() {
+        save_history(&mut rl, &history)?;
+        return Ok(replace_shell(&args, &mut state));
+    }
 
-pub fn setup_ctrlc_handler() {
-    ctrlc::set_handler(move || {
-        io::stdout().flush().unwrap();
-    })
-    .expect("Error setting Ctrl-C handler");
+    run_trap("EXIT", &mut state);
+    save_history(&mut rl, &history)?;
+    Ok(state.last_status)
 }
@@ -0,0 +1,263 @@
+use std::io;
+use std::process::ExitStatus;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+// Signals the interactive shell ignores, its foreground children get them instead
+#[cfg(unix)]
+const JOB_CONTROL_SIGNALS: [i32; 3] = [libc::SIGINT, libc::SIGQUIT, libc::SIGTSTP];
+
+// Signal names without the SIG prefix, for trap and kill
+#[cfg(unix)]
+pub const SIGNALS: [(&str, i32); 29] = [
+    ("HUP", libc::SIGHUP),
+    ("INT", libc::SIGINT),
+    ("QUIT", libc::SIGQUIT),
+    ("ILL", libc::SIGILL),
+    ("TRAP", libc::SIGTRAP),
+    ("ABRT", libc::SIGABRT),
+    ("BUS", libc::SIGBUS),
+    ("FPE", libc::SIGFPE),
+    ("KILL", libc::SIGKILL),
+    ("USR1", libc::SIGUSR1),
+    ("SEGV", libc::SIGSEGV),
+    ("USR2", libc::SIGUSR2),
+    ("PIPE", libc::SIGPIPE),
+    ("ALRM", libc::SIGALRM),
+    ("TERM", libc::SIGTERM),
+    ("CHLD", libc::SIGCHLD),
+    ("CONT", libc::SIGCONT),
+    ("STOP", libc::SIGSTOP),
+    ("TSTP", libc::SIGTSTP),
+    ("TTIN", libc::SIGTTIN),
+    ("TTOU", libc::SIGTTOU),
+    ("URG", libc::SIGURG),
+    ("XCPU", libc::SIGXCPU),
+    ("XFSZ", libc::SIGXFSZ),
+    ("VTALRM", libc::SIGVTALRM),
+    ("PROF", libc::SIGPROF),
+    ("WINCH", libc::SIGWINCH),
+    ("IO", libc::SIGIO),
+    ("SYS", libc::SIGSYS),
+];
+
+// Ctrl-C is the only signal a Windows console sends, the others are for kill
+#[cfg(windows)]
+pub const SIGNALS: [(&str, i32); 3] = [("INT", 2), ("KILL", 9), ("TERM", 15)];
+
+#[cfg(unix)]
+pub const SIGINT: i32 = libc::SIGINT;
+#[cfg(windows)]
+pub const SIGINT: i32 = 2;
+
+// Trapped signals that arrived since the shell last ran their handlers
+static PENDING: AtomicU64 = AtomicU64::new(0);
+
+// Signals ignored with `trap '' SIG`, children keep ignoring them
+#[cfg_attr(windows, allow(dead_code))]
+static IGNORED: AtomicU64 = AtomicU64::new(0);
+
+// What the shell does when a signal arrives
+pub enum Disposition {
+    Catch,   // record it so its trap runs between commands
+    Ignore,  // trap '' SIG
+    Default, // trap - SIG
+}
+
+// The list printed by trap -l and kill -l
+pub fn list_signals() {
+    for (name, number) in SIGNALS {
+        println!("{:2}) SIG{}", number, name);
+    }
+}
+
+pub fn signal_number(name: &str) -> Option<i32> {
+    let name = name.to_ascii_uppercase();
+    let name = name.strip_prefix("SIG").unwrap_or(&name);
+    SIGNALS
+        .iter()
+        .find(|(signal, _)| *signal == name)
+        .map(|&(_, number)| number)
+}
+
+pub fn signal_name(number: i32) -> Option<&'static str> {
+    SIGNALS
+        .iter()
+        .find(|&&(_, signal)| signal == number)
+        .map(|&(name, _)| name)
+}
+
+#[cfg(unix)]
+pub fn setup_signal_handlers() {
+    for signal in JOB_CONTROL_SIGNALS {
+        // SAFETY: SIG_IGN is a valid disposition for these signals
+        unsafe { libc::signal(signal, libc::SIG_IGN) };
+    }
+}
+
+#[cfg(windows)]
+pub fn setup_signal_handlers() {
+    use std::io::Write;
+
+    // Ctrl-C goes to every process on the console, keep the shell alive
+    ctrlc::set_handler(move || {
+        PENDING.fetch_or(1 << 2, Ordering::SeqCst);
+        io::stdout().flush().unwrap();
+    })
+    .expect("Error setting Ctrl-C handler");
+}
+
+#[cfg(unix)]
+extern "C" fn record_signal(signal: libc::c_int) {
+    PENDING.fetch_or(1 << signal, Ordering::SeqCst);
+}
+
+#[cfg(unix)]
+pub fn set_disposition(signal: i32, disposition: Disposition) -> io::Result<()> {
+    let handler = match disposition {
+        Disposition::Catch => record_signal as extern "C" fn(libc::c_int) as libc::sighandler_t,
+        Disposition::Ignore => libc::SIG_IGN,
+        // The shell's own default for job control signals is to ignore them
+        Disposition::Default if JOB_CONTROL_SIGNALS.contains(&signal) => libc::SIG_IGN,
+        Disposition::Default => libc::SIG_DFL,
+    };
+
+    // SAFETY: the handler only touches an atomic, which is async-signal-safe
+    if unsafe { libc::signal(signal, handler) } == libc::SIG_ERR {
+        return Err(io::Error::last_os_error());
+    }
+    match disposition {
+        Disposition::Ignore => IGNORED.fetch_or(1 << signal, Ordering::SeqCst),
+        _ => IGNORED.fetch_and(!(1 << signal), Ordering::SeqCst),
+    };
+    PENDING.fetch_and(!(1 << signal), Ordering::SeqCst);
+    Ok(())
+}
+
+// The Ctrl-C handler is always installed, the shell just doesn't act on it
+#[cfg(windows)]
+pub fn set_disposition(signal: i32, _disposition: Disposition) -> io::Result<()> {
+    PENDING.fetch_and(!(1 << signal), Ordering::SeqCst);
+    Ok(())
+}
+
+// A trapped signal that arrived and is waiting for its trap to run
+pub fn pending_signal() -> Option<i32> {
+    let pending = PENDING.load(Ordering::SeqCst);
+    (pending != 0).then(|| pending.trailing_zeros() as i32)
+}
+
+// The signals that arrived since the last call
+pub fn take_pending() -> Vec<i32> {
+    let pending = PENDING.swap(0, Ordering::SeqCst);
+    (1..64)
+        .filter(|signal| pending & (1 << signal) != 0)
+        .collect()
+}
+
+// Ignored dispositions survive exec, so children must get the defaults back,
+// unless a trap ignores the signal. Ctrl-Z stays ignored: without job control
+// the shell could never resume a stopped child. Called between fork and exec,
+// must stay async-signal-safe.
+#[cfg(unix)]
+pub fn restore_default_signals() {
+    let ignored = IGNORED.load(Ordering::SeqCst);
+    for signal in JOB_CONTROL_SIGNALS {
+        if signal != libc::SIGTSTP && ignored & (1 << signal) == 0 {
+            // SAFETY: signal() is async-signal-safe and SIG_DFL is always valid
+            unsafe { libc::signal(signal, libc::SIG_DFL) };
+        }
+    }
+}
+
+// Whether the child was stopped by Ctrl-C
+pub fn interrupted(status: ExitStatus) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        status.signal() == Some(libc::SIGINT)
+    }
+
+    #[cfg(windows)]
+    {
+        const STATUS_CONTROL_C_EXIT: u32 = 0xC000013A;
+        status.code().map(|code| code as u32) == Some(STATUS_CONTROL_C_EXIT)
+    }
+}
+
+// Exit status of a child, reporting how it died if a signal killed it.
+// Signal deaths are stored as 128 + signal number, like other shells do.
+pub fn exit_code(status: ExitStatus) -> i32 {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+
+        if let Some(signal) = status.signal() {
+            report_signal(signal, status.core_dumped());
+        }
+    }
+
+    status_code(status)
+}
+
+// Exit status of a child without reporting anything, for background jobs
+pub fn status_code(status: ExitStatus) -> i32 {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+
+        if let Some(signal) = status.signal() {
+            return 128 + signal;
+        }
+    }
+
+    status.code().unwrap_or(1)
+}
+
+#[cfg(unix)]
+fn report_signal(signal: i32, core_dumped: bool) {
+    // Interrupted and broken pipe deaths are expected, stay quiet like bash
+    if signal == libc::SIGINT || signal == libc::SIGPIPE {
+        return;
+    }
+
+    let description = describe(signal)
+        .map(String::from)
+        .unwrap_or_else(|| format!("Unknown signal {}", signal));
+
+    if core_dumped {
+        eprintln!("{} (core dumped)", description);
+    } else {
+        eprintln!("{}", description);
+    }
+}
+
+#[cfg(unix)]
+pub fn describe(signal: i32) -> Option<&'static str> {
+    let description = match signal {
+        libc::SIGHUP => "Hangup",
+        libc::SIGQUIT => "Quit",
+        libc::SIGILL => "Illegal instruction",
+        libc::SIGTRAP => "Trace/breakpoint trap",
+        libc::SIGABRT => "Aborted",
+        libc::SIGBUS => "Bus error",
+        libc::SIGFPE => "Floating point exception",
+        libc::SIGKILL => "Killed",
+        libc::SIGUSR1 => "User defined signal 1",
+        libc::SIGSEGV => "Segmentation fault",
+        libc::SIGUSR2 => "User defined signal 2",
+        libc::SIGALRM => "Alarm clock",
+        libc::SIGTERM => "Terminated",
+        libc::SIGXCPU => "CPU time limit exceeded",
+        libc::SIGXFSZ => "File size limit exceeded",
+        libc::SIGVTALRM => "Virtual timer expired",
+        libc::SIGPROF => "Profiling timer expired",
+        libc::SIGSYS => "Bad system call",
+        _ => return None,
+    };
+    Some(description)
+}
+
+#[cfg(windows)]
+pub fn describe(_signal: i32) -> Option<&'static str> {
+    None
+}
@@ -0,0 +1,185 @@
+use crate::shell::command::CommandExpr;
+use crate::shell::config::Config;
+use crate::shell::dirstack::DirStack;
+use crate::shell::frecency::{FrecencyDb, SharedFrecencyDb};
+use crate::shell::jobs::Job;
+use crate::shell::options::ShellOptions;
+use crate::shell::pathcache::{PathCache, SharedPathCache};
+use crate::shell::time::Usage;
+use std::collections::HashMap;
+use std::env;
+use std::path::PathBuf;
+use std::process::Command;
+
+pub struct ShellState {
+    pub config: Config,
+    pub dir_stack: DirStack,
+    pub frecency: SharedFrecencyDb,
+    pub path_cache: SharedPathCache,
+    pub last_status: i32,
+    pub interrupted: bool,      // a foreground command was stopped by Ctrl-C
+    pub aborted: bool,          // set -u found an unbound variable
+    pub stdin_redirected: bool, // a builtin's fd 0 is a pipe or here-document
+    pub options: ShellOptions,
+    pub errexit_exempt: bool, // the last failure was a condition, as in `false && x`
+    pub vars: HashMap<String, String>, // shell variables, exported ones live in `environment`
+    pub environment: HashMap<String, Option<String>>, // exports over the inherited environment, None is unset
+    pub pipestatus: Vec<i32>, // exit status of each stage of the last pipeline
+    pub loop_depth: usize,    // loops currently running, for break and continue
+    pub jump: Option<Jump>,   // set by break, continue and return until handled
+    pub functions: HashMap<String, CommandExpr>,
+    pub positional: Vec<String>, // $1, $2... of the running function
+    pub locals: Vec<HashMap<String, Option<String>>>, // per call, values hidden by `local`
+    pub traps: HashMap<String, String>, // trap commands by condition, as in EXIT or INT
+    pub in_trap: bool,           // a trap is running, others wait for it
+    pub condition_depth: usize,  // conditions being evaluated, they don't trigger ERR
+    pub jobs: Vec<Job>,          // background pipelines, oldest first
+    pub last_background: Option<u32>, // $!, pid of the last background command
+    pub exec: Option<Vec<String>>, // command `exec` replaces the shell with
+    pub child_usage: Usage,      // what children waited for since the innermost `time` started used
+}
+
+// Leave or restart the nth enclosing loop, or leave the running function
+#[derive(Clone, Copy)]
+pub enum Jump {
+    Break(usize),
+    Continue(usize),
+    Return,
+}
+
+impl ShellState {
+    pub fn new(config: Config) -> Self {
+        Self {
+            config,
+            dir_stack: DirStack::default(),
+            frecency: FrecencyDb::shared(),
+            path_cache: PathCache::shared(),
+            last_status: 0,
+            interrupted: false,
+            aborted: false,
+            stdin_redirected: false,
+            options: ShellOptions::default(),
+            errexit_exempt: false,
+            vars: HashMap::new(),
+            environment: HashMap::new(),
+            pipestatus: Vec::new(),
+            loop_depth: 0,
+            jump: None,
+            functions: HashMap::new(),
+            positional: Vec::new(),
+            locals: Vec::new(),
+            traps: HashMap::new(),
+            in_trap: false,
+            condition_depth: 0,
+            jobs: Vec::new(),
+            last_background: None,
+            exec: None,
+            child_usage: Usage::default(),
+        }
+    }
+
+    pub fn get_var(&self, name: &str) -> Option<String> {
+        match name {
+            "?" => Some(self.last_status.to_string()),
+            "$" => Some(std::process::id().to_string()),
+            "!" => self.last_background.map(|pid| pid.to_string()),
+            "0" => Some("ryn".to_string()),
+            "#" => Some(self.positional.len().to_string()),
+            "@" | "*" => Some(self.positional.join(" ")),
+            _ if name.chars().all(|c| c.is_ascii_digit()) => {
+                let index = name.parse::<usize>().ok()?;
+                self.positional.get(index.checked_sub(1)?).cloned()
+            }
+            "PIPESTATUS" => self.get_array(name).map(|values| values.join(" ")),
+            _ => self.vars.get(name).cloned().or_else(|| self.get_env(name)),
+        }
+    }
+
+    // Only PIPESTATUS is an array for now
+    pub fn get_array(&self, name: &str) -> Option<Vec<String>> {
+        match name {
+            "PIPESTATUS" => Some(self.pipestatus.iter().map(i32::to_string).collect()),
+            _ => None,
+        }
+    }
+
+    // Exported variables stay exported when they are assigned
+    pub fn set_var(&mut self, name: &str, value: String) {
+        if self.is_exported(name) {
+            self.set_env(name, value);
+        } else {
+            self.vars.insert(name.to_string(), value);
+        }
+    }
+
+    pub fn unset_var(&mut self, name: &str) {
+        self.vars.remove(name);
+        if env::var_os(name).is_some() {
+            self.environment.insert(name.to_string(), None);
+        } else {
+            self.environment.remove(name);
+        }
+        self.update_path(name);
+    }
+
+    // The shell never changes its own process environment, other threads may
+    // be reading it. Exports are kept here and handed to each child instead.
+    pub fn get_env(&self, name: &str) -> Option<String> {
+        match self.environment.get(name) {
+            Some(value) => value.clone(),
+            None => env::var(name).ok(),
+        }
+    }
+
+    fn is_exported(&self, name: &str) -> bool {
+        match self.environment.get(name) {
+            Some(value) => value.is_some(),
+            None => env::var_os(name).is_some(),
+        }
+    }
+
+    // Export a variable, moving it out of the shell variables
+    pub fn set_env(&mut self, name: &str, value: String) {
+        self.vars.remove(name);
+        self.environment.insert(name.to_string(), Some(value));
+        self.update_path(name);
+    }
+
+    // The environment children get, sorted by name
+    pub fn exported_vars(&self) -> Vec<(String, String)> {
+        let mut vars: Vec<(String, String)> = env::vars_os()
+            .filter_map(|(name, value)| Some((name.into_string().ok()?, value.into_string().ok()?)))
+            .filter(|(name, _)| !self.environment.contains_key(name))
+            .chain(
+                self.environment
+                    .iter()
+                    .filter_map(|(name, value)| Some((name.clone(), value.clone()?))),
+            )
+            .collect();
+        vars.sort();
+        vars
+    }
+
+    pub fn pass_env(&self, cmd: &mut Command) {
+        for (name, value) in &self.environment {
+            match value {
+                Some(value) => cmd.env(name, value),
+                None => cmd.env_remove(name),
+            };
+        }
+    }
+
+    pub fn home_dir(&self) -> Option<PathBuf> {
+        self.get_var("HOME")
+            .map(PathBuf::from)
+            .or_else(dirs::home_dir)
+    }
+
+    // Commands are looked up in the shell's PATH, not the inherited one
+    fn update_path(&self, name: &str) {
+        if name == "PATH" {
+            let path = self.get_env("PATH").map(Into::into);
+            self.path_cache.borrow_mut().set_path(path);
+        }
+    }
+}
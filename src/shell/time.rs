@@ -0,0 +1,197 @@
+use crate::shell::state::ShellState;
+use std::io;
+use std::process::{Child, ExitStatus};
+use std::time::{Duration, Instant};
+
+// The format of `time -p`
+pub const POSIX_FORMAT: &str = "real %2R\\nuser %2U\\nsys %2S";
+
+// CPU time and peak memory of some processes
+#[derive(Clone, Copy, Default)]
+pub struct Usage {
+    user: Duration,
+    sys: Duration,
+    max_rss: u64, // in kilobytes
+}
+
+impl Usage {
+    fn add(self, other: Usage) -> Usage {
+        Usage {
+            user: self.user + other.user,
+            sys: self.sys + other.sys,
+            max_rss: self.max_rss.max(other.max_rss),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn from_rusage(rusage: &libc::rusage) -> Usage {
+    let duration = |time: libc::timeval| {
+        Duration::from_secs(time.tv_sec as u64) + Duration::from_micros(time.tv_usec as u64)
+    };
+    // macOS reports bytes, other systems kilobytes
+    let max_rss = rusage.ru_maxrss as u64;
+    #[cfg(target_os = "macos")]
+    let max_rss = max_rss / 1024;
+
+    Usage {
+        user: duration(rusage.ru_utime),
+        sys: duration(rusage.ru_stime),
+        max_rss,
+    }
+}
+
+// Wait for a foreground child with wait4, adding what it used to the
+// children's usage `time` reports
+#[cfg(unix)]
+pub fn wait_child(child: &mut Child, state: &mut ShellState) -> io::Result<ExitStatus> {
+    use std::os::unix::process::ExitStatusExt;
+
+    let pid = child.id() as i32;
+    let mut status = 0;
+    // SAFETY: rusage is plain data, all zeroes is valid
+    let mut rusage: libc::rusage = unsafe { std::mem::zeroed() };
+    loop {
+        // SAFETY: status and rusage are valid for writes
+        if unsafe { libc::wait4(pid, &mut status, libc::WUNTRACED, &mut rusage) } == -1 {
+            let err = io::Error::last_os_error();
+            if err.kind() != io::ErrorKind::Interrupted {
+                return Err(err);
+            }
+            continue;
+        }
+        // A child that stops itself on Ctrl-Z is resumed, there are no
+        // stopped jobs to leave it in
+        if !libc::WIFSTOPPED(status) {
+            break;
+        }
+        // SAFETY: pid is our child, it hasn't been reaped
+        unsafe { libc::kill(pid, libc::SIGCONT) };
+    }
+    state.child_usage = state.child_usage.add(from_rusage(&rusage));
+    Ok(ExitStatus::from_raw(status))
+}
+
+#[cfg(not(unix))]
+pub fn wait_child(child: &mut Child, _state: &mut ShellState) -> io::Result<ExitStatus> {
+    child.wait()
+}
+
+// Builtins and expansions run in the shell itself
+#[cfg(unix)]
+fn shell_usage() -> Usage {
+    // SAFETY: rusage is plain data, all zeroes is valid
+    let mut rusage: libc::rusage = unsafe { std::mem::zeroed() };
+    // SAFETY: rusage is valid for writes
+    unsafe { libc::getrusage(libc::RUSAGE_SELF, &mut rusage) };
+    from_rusage(&rusage)
+}
+
+#[cfg(not(unix))]
+fn shell_usage() -> Usage {
+    Usage::default()
+}
+
+pub struct Timer {
+    start: Instant,
+    shell: Usage,
+    outer: Usage, // children of an enclosing `time` waited for so far
+}
+
+impl Timer {
+    pub fn start(state: &mut ShellState) -> Self {
+        Timer {
+            start: Instant::now(),
+            shell: shell_usage(),
+            outer: std::mem::take(&mut state.child_usage),
+        }
+    }
+
+    // The report for the timed command, following the format
+    pub fn finish(self, format: &str, state: &mut ShellState) -> String {
+        let real = self.start.elapsed();
+        let children = std::mem::take(&mut state.child_usage);
+        // An enclosing `time` counts these children too
+        state.child_usage = self.outer.add(children);
+
+        let shell = shell_usage();
+        let user = children.user + shell.user.saturating_sub(self.shell.user);
+        let sys = children.sys + shell.sys.saturating_sub(self.shell.sys);
+        format_times(format, real, user, sys, children.max_rss)
+    }
+}
+
+// Expands a TIMEFORMAT-style format: %[p][l]R, %[p][l]U and %[p][l]S for the
+// real, user and sys seconds with p decimals, %P for the CPU percentage, %M
+// for the max RSS in kilobytes and %% for a percent sign. \n and \t are
+// expanded too, so the format fits on one config line.
+fn format_times(format: &str, real: Duration, user: Duration, sys: Duration, rss: u64) -> String {
+    let mut output = String::new();
+    let mut chars = format.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('n') => output.push('\n'),
+                Some('t') => output.push('\t'),
+                Some(other) => output.push(other),
+                None => output.push('\\'),
+            },
+            '%' => {
+                let precision = chars
+                    .next_if(char::is_ascii_digit)
+                    .and_then(|digit| digit.to_digit(10))
+                    .map_or(3, |digit| digit.min(3) as usize);
+                let long = chars.next_if_eq(&'l').is_some();
+                let duration = match chars.next() {
+                    Some('R') => real,
+                    Some('U') => user,
+                    Some('S') => sys,
+                    Some('P') => {
+                        let cpu = (user + sys).as_secs_f64();
+                        let real = real.as_secs_f64();
+                        let percent = if real > 0.0 { cpu / real * 100.0 } else { 0.0 };
+                        output.push_str(&format!("{:.2}", percent));
+                        continue;
+                    }
+                    Some('M') => {
+                        output.push_str(&rss.to_string());
+                        continue;
+                    }
+                    Some('%') => {
+                        output.push('%');
+                        continue;
+                    }
+                    // Unknown conversions are kept as written
+                    Some(other) => {
+                        output.push('%');
+                        output.push(other);
+                        continue;
+                    }
+                    None => {
+                        output.push('%');
+                        continue;
+                    }
+                };
+                output.push_str(&seconds(duration, precision, long));
+            }
+            c => output.push(c),
+        }
+    }
+    output
+}
+
+// 1.234 or, in the long form, 0m1.234s
+fn seconds(duration: Duration, precision: usize, long: bool) -> String {
+    let secs = duration.as_secs_f64();
+    if !long {
+        return format!("{:.*}", precision, secs);
+    }
+    let minutes = duration.as_secs() / 60;
+    format!(
+        "{}m{:.*}s",
+        minutes,
+        precision,
+        secs - (minutes * 60) as f64
+    )
+}
@@ -0,0 +1,157 @@
+use crate::shell::eval::{EvalResult, eval_expr};
+use crate::shell::expand::quote;
+use crate::shell::parser::{parse_expr, tokenize};
+use crate::shell::signal::{
+    Disposition, list_signals, set_disposition, signal_name, signal_number, take_pending,
+};
+use crate::shell::state::ShellState;
+
+// Conditions that aren't signals: leaving the shell, a failed command and
+// every simple command
+const PSEUDO_SIGNALS: [&str; 3] = ["EXIT", "ERR", "DEBUG"];
+
+// The name a trap is stored under: EXIT, ERR, DEBUG or a signal without SIG
+fn condition_name(spec: &str) -> Option<String> {
+    let upper = spec.to_ascii_uppercase();
+    if spec == "0" {
+        return Some("EXIT".to_string());
+    }
+    if PSEUDO_SIGNALS.contains(&upper.as_str()) {
+        return Some(upper);
+    }
+    let number = match spec.parse::<i32>() {
+        Ok(number) => number,
+        Err(_) => signal_number(spec)?,
+    };
+    signal_name(number).map(String::from)
+}
+
+// trap [-lp] [[command] condition...]
+pub fn trap(args: &[String], state: &mut ShellState) -> i32 {
+    match args.first().map(String::as_str) {
+        None => return print_traps(&[], state),
+        Some("-p") => return print_traps(&args[1..], state),
+        Some("-l") => {
+            list_signals();
+            return 0;
+        }
+        _ => {}
+    }
+
+    let args = args.strip_prefix(&["--".to_string()]).unwrap_or(args);
+    // `trap INT` and `trap 2 15` reset like `trap - INT`
+    let (command, conditions) = match args.split_first() {
+        Some((first, rest)) if rest.is_empty() || first.parse::<u32>().is_ok() => ("-", args),
+        Some((command, conditions)) => (command.as_str(), conditions),
+        None => return 0,
+    };
+
+    let mut status = 0;
+    for spec in conditions {
+        let Some(name) = condition_name(spec) else {
+            eprintln!("trap: {}: invalid signal specification", spec);
+            status = 1;
+            continue;
+        };
+
+        if let Some(signal) = signal_number(&name) {
+            let disposition = match command {
+                "-" => Disposition::Default,
+                "" => Disposition::Ignore,
+                _ => Disposition::Catch,
+            };
+            if let Err(err) = set_disposition(signal, disposition) {
+                eprintln!("trap: {}: {}", spec, err);
+                status = 1;
+                continue;
+            }
+        }
+
+        if command == "-" {
+            state.traps.remove(&name);
+        } else {
+            state.traps.insert(name, command.to_string());
+        }
+    }
+    status
+}
+
+fn print_traps(specs: &[String], state: &ShellState) -> i32 {
+    let mut names = Vec::new();
+    let mut status = 0;
+    for spec in specs {
+        match condition_name(spec) {
+            Some(name) => names.push(name),
+            None => {
+                eprintln!("trap: {}: invalid signal specification", spec);
+                status = 1;
+            }
+        }
+    }
+    if specs.is_empty() {
+        names = state.traps.keys().cloned().collect();
+        names.sort_by_key(|name| signal_number(name).unwrap_or(0));
+    }
+
+    for name in names {
+        if let Some(command) = state.traps.get(&name) {
+            let shown = match signal_number(&name) {
+                Some(_) => format!("SIG{}", name),
+                None => name,
+            };
+            println!("trap -- {} {}", quote(command), shown);
+        }
+    }
+    status
+}
+
+// Run the trap for a condition through the evaluator. $? is left as it was,
+// unless the trap exits the shell.
+pub fn run_trap(name: &str, state: &mut ShellState) -> Option<EvalResult> {
+    let command = state.traps.get(name)?.clone();
+    if command.is_empty() || state.in_trap {
+        return None;
+    }
+
+    let expr = match tokenize(&command).and_then(|mut tokens| parse_expr(&mut tokens)) {
+        Ok(expr) => expr,
+        Err(err) => {
+            eprintln!("trap: {}", err);
+            return None;
+        }
+    };
+
+    let status = state.last_status;
+    let interrupted = std::mem::take(&mut state.interrupted);
+    let aborted = std::mem::take(&mut state.aborted);
+    state.in_trap = true;
+    let result = eval_expr(expr, state);
+    state.in_trap = false;
+    state.interrupted |= interrupted;
+    state.aborted |= aborted;
+
+    match result {
+        Some(result) if result.should_exit => Some(result),
+        _ => {
+            state.last_status = status;
+            None
+        }
+    }
+}
+
+// Run the traps of the signals that arrived, returns the result of one that
+// exits the shell
+pub fn run_pending_traps(state: &mut ShellState) -> Option<EvalResult> {
+    if state.in_trap {
+        return None;
+    }
+    for signal in take_pending() {
+        let Some(name) = signal_name(signal) else {
+            continue;
+        };
+        if let Some(result) = run_trap(name, state) {
+            return Some(result);
+        }
+    }
+    None
+}
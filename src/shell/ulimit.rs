@@ -0,0 +1,240 @@
+// ulimit [-SHa] [-cdflmnstuv] [limit]. Limits belong to the shell process,
+// so the commands it starts inherit them.
+#[cfg(unix)]
+pub fn ulimit(args: &[String]) -> i32 {
+    let mut which = Which::Both;
+    let mut all = false;
+    let mut resources = Vec::new();
+    let mut values = Vec::new();
+
+    for arg in args {
+        let Some(flags) = arg.strip_prefix('-').filter(|flags| !flags.is_empty()) else {
+            values.push(arg.as_str());
+            continue;
+        };
+        for flag in flags.chars() {
+            match flag {
+                'S' => which = Which::Soft,
+                'H' => which = Which::Hard,
+                'a' => all = true,
+                _ => match LIMITS.iter().find(|limit| limit.flag == flag) {
+                    Some(limit) => resources.push(limit),
+                    None => {
+                        eprintln!("ulimit: -{}: invalid option", flag);
+                        eprintln!("ulimit: usage: ulimit [-SHa] [-cdflmnstuv] [limit]");
+                        return 2;
+                    }
+                },
+            }
+        }
+    }
+
+    if all {
+        return print_limits(&LIMITS.iter().collect::<Vec<_>>(), which, true);
+    }
+    // The file size limit is the default, as in other shells
+    if resources.is_empty() {
+        resources.extend(LIMITS.iter().find(|limit| limit.flag == 'f'));
+    }
+    match values.as_slice() {
+        [] => print_limits(&resources, which, resources.len() > 1),
+        [value] if resources.len() == 1 => set_limit(resources[0], which, value),
+        _ => {
+            eprintln!("ulimit: too many arguments");
+            2
+        }
+    }
+}
+
+#[cfg(not(unix))]
+pub fn ulimit(_args: &[String]) -> i32 {
+    eprintln!("ulimit: not supported on this platform");
+    1
+}
+
+#[cfg(all(target_os = "linux", target_env = "gnu"))]
+type Resource = libc::__rlimit_resource_t;
+#[cfg(all(unix, not(all(target_os = "linux", target_env = "gnu"))))]
+type Resource = libc::c_int;
+
+#[cfg(unix)]
+struct Limit {
+    flag: char,
+    description: &'static str,
+    unit: &'static str,
+    scale: libc::rlim_t, // limit values are in units of this many bytes
+    resource: Resource,
+}
+
+#[cfg(unix)]
+static LIMITS: [Limit; 10] = [
+    Limit {
+        flag: 'c',
+        description: "core file size",
+        unit: "blocks",
+        scale: 1024,
+        resource: libc::RLIMIT_CORE,
+    },
+    Limit {
+        flag: 'd',
+        description: "data seg size",
+        unit: "kbytes",
+        scale: 1024,
+        resource: libc::RLIMIT_DATA,
+    },
+    Limit {
+        flag: 'f',
+        description: "file size",
+        unit: "blocks",
+        scale: 1024,
+        resource: libc::RLIMIT_FSIZE,
+    },
+    Limit {
+        flag: 'l',
+        description: "max locked memory",
+        unit: "kbytes",
+        scale: 1024,
+        resource: libc::RLIMIT_MEMLOCK,
+    },
+    Limit {
+        flag: 'm',
+        description: "max memory size",
+        unit: "kbytes",
+        scale: 1024,
+        resource: libc::RLIMIT_RSS,
+    },
+    Limit {
+        flag: 'n',
+        description: "open files",
+        unit: "files",
+        scale: 1,
+        resource: libc::RLIMIT_NOFILE,
+    },
+    Limit {
+        flag: 's',
+        description: "stack size",
+        unit: "kbytes",
+        scale: 1024,
+        resource: libc::RLIMIT_STACK,
+    },
+    Limit {
+        flag: 't',
+        description: "cpu time",
+        unit: "seconds",
+        scale: 1,
+        resource: libc::RLIMIT_CPU,
+    },
+    Limit {
+        flag: 'u',
+        description: "max user processes",
+        unit: "processes",
+        scale: 1,
+        resource: libc::RLIMIT_NPROC,
+    },
+    Limit {
+        flag: 'v',
+        description: "virtual memory",
+        unit: "kbytes",
+        scale: 1024,
+        resource: libc::RLIMIT_AS,
+    },
+];
+
+// -S and -H pick a limit, without either ulimit shows the soft one and sets both
+#[cfg(unix)]
+#[derive(Clone, Copy, PartialEq)]
+enum Which {
+    Soft,
+    Hard,
+    Both,
+}
+
+#[cfg(unix)]
+fn get_limit(limit: &Limit) -> std::io::Result<libc::rlimit> {
+    let mut rlimit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    // SAFETY: rlimit is valid for writes
+    if unsafe { libc::getrlimit(limit.resource, &mut rlimit) } == -1 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(rlimit)
+}
+
+#[cfg(unix)]
+fn print_limits(limits: &[&Limit], which: Which, describe: bool) -> i32 {
+    let mut status = 0;
+    for limit in limits {
+        let rlimit = match get_limit(limit) {
+            Ok(rlimit) => rlimit,
+            Err(err) => {
+                eprintln!("ulimit: {}: {}", limit.description, err);
+                status = 1;
+                continue;
+            }
+        };
+        let value = match which {
+            Which::Hard => rlimit.rlim_max,
+            _ => rlimit.rlim_cur,
+        };
+        let value = match value {
+            libc::RLIM_INFINITY => "unlimited".to_string(),
+            value => (value / limit.scale).to_string(),
+        };
+
+        if describe {
+            let unit = format!("({}, -{})", limit.unit, limit.flag);
+            println!("{:<28}{:<15} {}", limit.description, unit, value);
+        } else {
+            println!("{}", value);
+        }
+    }
+    status
+}
+
+// A number in the limit's unit, unlimited, or the current soft or hard limit
+#[cfg(unix)]
+fn set_limit(limit: &Limit, which: Which, value: &str) -> i32 {
+    let mut rlimit = match get_limit(limit) {
+        Ok(rlimit) => rlimit,
+        Err(err) => {
+            eprintln!("ulimit: {}: {}", limit.description, err);
+            return 1;
+        }
+    };
+
+    let value = match value {
+        "unlimited" => libc::RLIM_INFINITY,
+        "soft" => rlimit.rlim_cur,
+        "hard" => rlimit.rlim_max,
+        _ => match value
+            .parse::<libc::rlim_t>()
+            .ok()
+            .and_then(|value| value.checked_mul(limit.scale))
+        {
+            Some(value) => value,
+            None => {
+                eprintln!("ulimit: {}: invalid number", value);
+                return 1;
+            }
+        },
+    };
+    if which != Which::Hard {
+        rlimit.rlim_cur = value;
+    }
+    if which != Which::Soft {
+        rlimit.rlim_max = value;
+    }
+
+    // SAFETY: rlimit is a valid limit structure
+    if unsafe { libc::setrlimit(limit.resource, &rlimit) } == -1 {
+        let err = std::io::Error::last_os_error();
+        eprintln!(
+            "ulimit: {}: cannot modify limit: {}",
+            limit.description, err
+        );
+        return 1;
+    }
+    0
+}
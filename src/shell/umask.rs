@@ -0,0 +1,134 @@
+// umask [-pS] [mode]. The mask is octal, like 022, or symbolic, like
+// u=rwx,g=rx,o=, where the symbols are the permissions that stay allowed.
+#[cfg(unix)]
+pub fn umask(args: &[String]) -> i32 {
+    let mut symbolic = false;
+    let mut reusable = false;
+    let mut args = args;
+    while let Some(flags) = args
+        .first()
+        .and_then(|arg| arg.strip_prefix('-'))
+        .filter(|flags| !flags.is_empty())
+    {
+        for flag in flags.chars() {
+            match flag {
+                'S' => symbolic = true,
+                'p' => reusable = true,
+                _ => {
+                    eprintln!("umask: -{}: invalid option", flag);
+                    eprintln!("umask: usage: umask [-pS] [mode]");
+                    return 2;
+                }
+            }
+        }
+        args = &args[1..];
+    }
+
+    let current = current_mask();
+    let Some(mode) = args.first() else {
+        let mask = if symbolic {
+            format!("-S {}", symbolic_mask(current))
+        } else {
+            format!("{:04o}", current)
+        };
+        // -p prints it as a command that restores it
+        if reusable {
+            println!("umask {}", mask);
+        } else {
+            println!("{}", mask.trim_start_matches("-S "));
+        }
+        return 0;
+    };
+
+    let mask = match mode.chars().next() {
+        Some('0'..='7') => libc::mode_t::from_str_radix(mode, 8)
+            .ok()
+            .filter(|mask| *mask <= 0o777),
+        _ => parse_symbolic(mode, current),
+    };
+    let Some(mask) = mask else {
+        eprintln!("umask: {}: invalid mode", mode);
+        return 1;
+    };
+    // SAFETY: umask only changes the process's file creation mask
+    unsafe { libc::umask(mask) };
+    0
+}
+
+#[cfg(not(unix))]
+pub fn umask(_args: &[String]) -> i32 {
+    eprintln!("umask: not supported on this platform");
+    1
+}
+
+// umask(2) always sets the mask, so it is put back right away
+#[cfg(unix)]
+fn current_mask() -> libc::mode_t {
+    // SAFETY: umask only changes the process's file creation mask
+    unsafe {
+        let mask = libc::umask(0);
+        libc::umask(mask);
+        mask
+    }
+}
+
+// Who each symbol applies to, as the read, write and execute bits
+#[cfg(unix)]
+const CLASSES: [(char, libc::mode_t); 3] = [('u', 0o700), ('g', 0o070), ('o', 0o007)];
+
+#[cfg(unix)]
+fn symbolic_mask(mask: libc::mode_t) -> String {
+    let allowed = !mask & 0o777;
+    let classes: Vec<String> = CLASSES
+        .iter()
+        .map(|(class, bits)| {
+            let permissions: String = [('r', 0o444), ('w', 0o222), ('x', 0o111)]
+                .iter()
+                .filter(|(_, permission)| allowed & bits & permission != 0)
+                .map(|(symbol, _)| *symbol)
+                .collect();
+            format!("{}={}", class, permissions)
+        })
+        .collect();
+    classes.join(",")
+}
+
+// Applies clauses like u+w,go-rx or a=r to the allowed permissions
+#[cfg(unix)]
+fn parse_symbolic(mode: &str, mask: libc::mode_t) -> Option<libc::mode_t> {
+    let mut allowed = !mask & 0o777;
+
+    for clause in mode.split(',') {
+        let mut chars = clause.chars().peekable();
+        let mut who = 0;
+        while let Some(class) = chars.next_if(|c| "ugoa".contains(*c)) {
+            who |= match class {
+                'a' => 0o777,
+                _ => CLASSES.iter().find(|(c, _)| *c == class)?.1,
+            };
+        }
+        if who == 0 {
+            who = 0o777;
+        }
+
+        // Each clause has one or more operators, as in u+r-w
+        chars.peek()?;
+        while let Some(op) = chars.next() {
+            let mut permissions = 0;
+            while let Some(symbol) = chars.next_if(|c| "rwx".contains(*c)) {
+                permissions |= match symbol {
+                    'r' => 0o444,
+                    'w' => 0o222,
+                    _ => 0o111,
+                };
+            }
+            match op {
+                '+' => allowed |= who & permissions,
+                '-' => allowed &= !(who & permissions),
+                '=' => allowed = (allowed & !who) | (who & permissions),
+                _ => return None,
+            }
+        }
+    }
+    Some(!allowed & 0o777)
+}
@@ -0,0 +1,41 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+// Runs a script through ryn's stdin, with its history and config kept in a
+// directory of their own
+fn run(name: &str, script: &str) -> String {
+    let home = std::env::temp_dir().join(format!("ryn-test-{}-{}", name, std::process::id()));
+    let mut child = Command::new(env!("CARGO_BIN_EXE_ryn"))
+        .env("HOME", &home)
+        .env("XDG_CONFIG_HOME", home.join("config"))
+        .env("XDG_DATA_HOME", home.join("data"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .expect("ryn should start");
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(script.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    std::fs::remove_dir_all(&home).ok();
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+#[test]
+fn read_from_a_pipe() {
+    let output = run("pipe", "echo hi | read x; echo \"x=$x\"\necho next\n");
+    assert!(output.contains("x=hi\n"), "{:?}", output);
+    // The script's next line is still run, not read
+    assert!(output.contains("next\n"), "{:?}", output);
+}
+
+#[test]
+fn read_from_a_here_string() {
+    let output = run("herestring", "read y <<< there; echo \"y=$y\"\necho next\n");
+    assert!(output.contains("y=there\n"), "{:?}", output);
+    assert!(output.contains("next\n"), "{:?}", output);
+}